@@ -1,27 +1,78 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    rc::Rc,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use actix::{fut, Actor, AsyncContext, Context, Handler, Message, Recipient};
 use actix_web::rt::time as RuntimeTime;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    future::{join, join_all, select, Either},
+    lock::Mutex,
+    StreamExt,
+};
 use web3::{
-    transports,
-    types::{FilterBuilder, H160},
+    error::Error as Web3Error,
+    types::{Block, BlockId, Log, H160, H256},
     Web3,
 };
 
 use crate::{
-    datas::{config::ContractConfig, data::AppData, BoxedResult},
-    xprotocol::{factory::Factory, proposals::Proposal, router::Router, ModuleTest},
+    datas::{
+        config::ContractConfig,
+        data::AppData,
+        handle::{LeaderboardWindow, ProposalAduitState, ProposalState},
+        BoxedResult,
+    },
+    xprotocol::{
+        events::{Event, EventRegistry, EventSource},
+        factory::Factory,
+        filter::LogFilter,
+        oracle::{AdminOracle, ContractOracle, Oracle, PriceOracle},
+        outcome::OutcomeToken,
+        pnl::Pnl,
+        proposals::Proposal,
+        router::Router,
+        subscribe::Subscriber,
+        transport::Failover,
+        volume::RollingVolume,
+        ModuleTest, PendingEvent, ProposalStatus,
+    },
 };
 
 use super::ws::SubOpCode;
 
+// 保留最近多少个区块的hash用于回滚检测
+const REORG_DEPTH: u64 = 1000;
+// 美元价格的刷新间隔(秒)
+const ORACLE_INTERVAL: u64 = 5 * 60;
+// rpc节点健康检查的间隔(秒)
+const RPC_CHECK_INTERVAL: u64 = 30;
+// 每次检索区块数的上下限,少于2个区块时tick不会检索日志
+const MIN_STEP: u64 = 2;
+const MAX_STEP: u64 = 10_000;
+// 日志数量少于STEP_GROW_LOGS且耗时少于STEP_FAST时扩大检索区间
+const STEP_GROW_LOGS: usize = 1000;
+const STEP_FAST: Duration = Duration::from_secs(2);
+// 节点限制单次日志数量或区块范围时返回的错误码(Infura等)
+const RANGE_ERROR_CODE: i64 = -32005;
+// geth/infura, alchemy, bsc/erigon, ankr, quicknode, besu限制日志数量或区块范围时的错误信息
+const RANGE_ERROR_MESSAGES: [&str; 8] = [
+    "query returned more than",
+    "log response size exceeded",
+    "exceed maximum block range",
+    "block range is too wide",
+    "block range too large",
+    "is limited to a",
+    "requested range exceeds",
+    "query timeout exceeded",
+];
+
 #[derive(Message)]
 #[rtype("()")]
 pub struct Ping(pub u64);
@@ -29,30 +80,76 @@ pub struct Ping(pub u64);
 #[derive(Clone)]
 pub struct BlockActor {
     data: Arc<AppData>,
-    web3: Arc<Web3<transports::Http>>,
+    web3: Arc<Web3<Failover>>,
     from_block: Arc<AtomicU64>,
+    pending_block: Arc<AtomicU64>,
+    confirmations: u64,
+    //落后较多时并发检索的区间数
+    parallelism: usize,
     start: Arc<AtomicU64>,
+    oracle_refreshed: Arc<AtomicU64>,
+    rpc_checked: Arc<AtomicU64>,
     rec_ping: Option<Recipient<Ping>>,
-    module_test: Arc<HashMap<H160, Box<dyn ModuleTest>>>,
+    factory: H160,
+    router: H160,
+    //已对账的工厂提案ID
+    reconciled: Arc<AtomicU64>,
+    module_test: Rc<HashMap<H160, Box<dyn ModuleTest>>>,
+    oracle: Rc<dyn PriceOracle>,
+    //按topic0分发日志
+    registry: Rc<EventRegistry>,
+    //提案事件的签名
+    topics: Rc<Vec<H256>>,
+    ws: Option<String>,
+    //websocket收到新区块或日志的通知
+    wake: Option<Rc<Mutex<UnboundedReceiver<()>>>>,
 }
 
 impl BlockActor {
-    pub fn new(data: Arc<AppData>, contract: &ContractConfig) -> BoxedResult<Self> {
-        let transport = transports::Http::new(&contract.rpc)?;
-        let web3 = Arc::new(Web3::new(transport));
+    pub async fn new(data: Arc<AppData>, contract: &ContractConfig) -> BoxedResult<Self> {
+        let web3 = Arc::new(Web3::new(data.rpc.clone()));
 
         let factory = contract.factory.parse::<H160>()?;
         let router = contract.router.parse::<H160>()?;
         let mut module_test = HashMap::<H160, Box<dyn ModuleTest>>::new();
-        module_test.insert(factory, Box::new(Factory::new(data.clone(), web3.clone())));
-        module_test.insert(router, Box::new(Router::new(data.clone(), web3.clone())));
+        module_test.insert(
+            factory,
+            Box::new(Factory::new(data.clone(), web3.clone(), contract.calldata_fallback)),
+        );
+        module_test.insert(
+            router,
+            Box::new(Router::new(data.clone(), web3.clone(), contract.calldata_fallback)),
+        );
+        let registry = EventRegistry::load().map_err(|e| e.to_string())?;
+        //配置了定价合约则从链上读取美元价格,否则使用管理员提交的价格
+        let oracle: Rc<dyn PriceOracle> = match &contract.oracle {
+            Some(oracle) => Rc::new(
+                ContractOracle::new(&web3, oracle.parse::<H160>()?)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            ),
+            None => Rc::new(AdminOracle::new(data.clone())),
+        };
         let obj = Self {
             data,
             web3,
             from_block: Arc::new(AtomicU64::new(0)),
+            pending_block: Arc::new(AtomicU64::new(0)),
+            confirmations: contract.confirmations,
+            parallelism: contract.backfill_parallelism.max(1),
             start: Arc::new(AtomicU64::new(0)),
+            oracle_refreshed: Arc::new(AtomicU64::new(0)),
+            rpc_checked: Arc::new(AtomicU64::new(0)),
             rec_ping: None,
-            module_test: Arc::new(module_test),
+            factory,
+            router,
+            reconciled: Arc::new(AtomicU64::new(0)),
+            module_test: Rc::new(module_test),
+            oracle,
+            topics: Rc::new(registry.topics(EventSource::Proposal)),
+            registry: Rc::new(registry),
+            ws: contract.ws.clone(),
+            wake: None,
         };
         Ok(obj)
     }
@@ -72,12 +169,26 @@ impl BlockActor {
 
     /*
         获取链最新block,更新data的block
+        只检索确认数足够的区块,未确认的区块推送到pendingProposal
+        返回下一次检索的区块数
     */
-    pub async fn tick(&self, block_step: u64) -> BoxedResult<()> {
+    pub async fn tick(&self, block_step: u64) -> BoxedResult<u64> {
         let web3 = self.web3.clone();
 
-        let block = web3.eth().block_number().await?.as_u64();
-        self.update_data_block(block);
+        //定时检查所有节点的最新区块,落后的节点不参与请求
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        if self.rpc_checked.load(Ordering::Relaxed) + RPC_CHECK_INTERVAL < now {
+            web3.transport().check().await;
+            self.rpc_checked.swap(now, Ordering::Relaxed);
+        }
+        let head = web3.eth().block_number().await?.as_u64();
+        self.update_data_block(head);
+        let block = head.saturating_sub(self.confirmations);
+        if let Err(e) = self.pending_update(block, head).await {
+            log::error!("pending update error: {:?}", e);
+        }
 
         let from_block = self.from_block.load(Ordering::Relaxed);
         let mut to_block = from_block + block_step - 1; //from 0 100 99  1000
@@ -85,80 +196,823 @@ impl BlockActor {
             to_block = block;
         }
         
-        log::info!("from {} to {}, total {}", from_block, to_block, block);
+        log::info!("from {} to {}, total {}", from_block, to_block, head);
 
         if from_block >= to_block {
             // from是最新区块，检查热度1小时
-            self.normal_update(block).await
+            self.normal_update(block, block_step).await?;
+            Ok(block_step)
         } else {
+            //链发生回滚则先回退数据，下一次从分叉点重新检索
+            if self.check_reorg(from_block).await? {
+                return Ok(block_step);
+            }
             //from不是最新区块，更新合约日志，检查热度
-            self.log_update(from_block, to_block, block).await
+            self.log_update(from_block, to_block, block, block_step).await
+        }
+    }
+
+    /*
+        推送(block, head]之间未确认的提案日志,不写入数据库
+    */
+    async fn pending_update(&self, block: u64, head: u64) -> BoxedResult<()> {
+        if block >= head {
+            return Ok(());
+        }
+        let pending_block = self.pending_block.load(Ordering::Relaxed);
+        //链变短说明发生了回滚,重新推送
+        let from_block = if pending_block > head {
+            block + 1
+        } else {
+            (block + 1).max(pending_block + 1)
+        };
+        if from_block > head {
+            return Ok(());
+        }
+
+        let contracts = self.module_test.keys().cloned().collect::<Vec<H160>>();
+        let logs =
+            LogFilter::logs(&self.web3, from_block, head, &contracts, &self.topics).await?;
+
+        for log in logs.iter() {
+            let proposal_id = self.data.get_proposal_id(&log.address);
+            let source = match (self.module_test.get(&log.address), proposal_id) {
+                (Some(module), _) => module.source(),
+                (None, Some(_)) => EventSource::Proposal,
+                (None, None) => continue,
+            };
+            if let Some(event) = PendingEvent::from_log(&self.registry, source, log, proposal_id) {
+                self.data
+                    .push_to_client("pendingProposal", SubOpCode::Add, event);
+            }
+        }
+        self.pending_block.swap(head, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /*
+        检查from_block的parent hash是否与数据库记录的上一区块hash一致
+        不一致则找到分叉点并回滚
+    */
+    async fn check_reorg(&self, from_block: u64) -> BoxedResult<bool> {
+        let store = &self.data.store;
+        let parent = from_block.saturating_sub(1);
+        let stored = match store.read_block_hash(parent).await? {
+            Some(hash) => hash,
+            None => return Ok(false),
+        };
+        let block = self
+            .web3
+            .eth()
+            .block(BlockId::Number(from_block.into()))
+            .await?
+            .ok_or("empty block")?;
+        if format!("{:?}", block.parent_hash) == stored {
+            return Ok(false);
+        }
+        let fork = self.find_fork_block(parent).await?;
+        log::warn!("reorg detected at {}, rollback to {}", from_block, fork);
+        self.rollback(fork).await?;
+        Ok(true)
+    }
+
+    /*
+        从高到低比较数据库记录的hash和链上hash,第一个一致的区块即为分叉点
+    */
+    async fn find_fork_block(&self, from_block: u64) -> BoxedResult<u64> {
+        let hashes = self.data.store.read_block_hashes(from_block).await?;
+        for (number, hash) in hashes.iter() {
+            let block = self
+                .web3
+                .eth()
+                .block(BlockId::Number((*number).into()))
+                .await?;
+            if let Some(chain_hash) = block.and_then(|b| b.hash) {
+                if &format!("{:?}", chain_hash) == hash {
+                    return Ok(*number);
+                }
+            }
+        }
+        // 记录的hash全部失效,回退到最早记录之前
+        Ok(hashes
+            .last()
+            .map(|(number, _)| number.saturating_sub(1))
+            .unwrap_or(0))
+    }
+
+    /*
+        删除分叉点之后写入的提案,关系,价格
+        路由事件修改过的状态和审核状态恢复为分叉点的链上状态
+        重新读取受影响提案在分叉点的交易额和流动性
+    */
+    async fn rollback(&self, fork: u64) -> BoxedResult<()> {
+        let data = self.data.clone();
+        let (removed, touched, restated) =
+            data.store.rollback_to_block(data.chain_id, fork).await?;
+        //移除提案时一并注销其选项代币
+        for addr in removed.iter() {
+            if let Ok(addr) = addr.parse::<H160>() {
+                data.remove_proposal(&addr);
+            }
+        }
+        for proposal_id in restated {
+            if let Err(e) = self.restore_state(proposal_id, fork).await {
+                log::error!("restore proposal {} state error: {:?}", proposal_id, e);
+            }
+        }
+        let addrs = touched
+            .iter()
+            .filter_map(|&proposal_id| data.get_proposal_address(proposal_id))
+            .collect::<Vec<H160>>();
+        Proposal::refresh_totals(&data, &self.web3, addrs, fork)
+            .await
+            .map_err(|e| e.to_string())?;
+        RollingVolume::rebuild(&data, &self.web3, fork)
+            .await
+            .map_err(|e| e.to_string())?;
+        Oracle::apply(&data).await.map_err(|e| e.to_string())?;
+        Pnl::replay(&data, touched)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.from_block.swap(fork + 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /*
+        读取提案在分叉点的状态,写回数据库和data
+        Original未审核,Formal审核通过,分叉点已结束的提案不会有回滚的状态变化
+    */
+    async fn restore_state(&self, proposal_id: u64, fork: u64) -> BoxedResult<()> {
+        let data = &self.data;
+        let address = match data.get_proposal_address(proposal_id) {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        let state = Proposal::state_at(&self.web3, address, BlockId::Number(fork.into()))
+            .await
+            .map_err(|e| e.to_string())?;
+        let audit_state = match state {
+            ProposalState::Original => Some(ProposalAduitState::NotReviewed),
+            ProposalState::Formal => Some(ProposalAduitState::Passed),
+            ProposalState::End => None,
+        };
+        if let Some(audit_state) = audit_state {
+            data.store
+                .write_proposal_audit_state(format!("{:?}", address), format!("{:?}", audit_state))
+                .await?;
         }
+        data.store
+            .write_proposal_state(format!("{:?}", proposal_id), format!("{:?}", state), fork)
+            .await?;
+        data.set_proposal_state(proposal_id, state);
+        data.push_to_client(
+            "proposalStatus",
+            SubOpCode::Update,
+            ProposalStatus::new(proposal_id, address, state),
+        );
+        Ok(())
     }
 
-    async fn normal_update(&self, block: u64) -> BoxedResult<()> {
+    async fn normal_update(&self, block: u64, block_step: u64) -> BoxedResult<()> {
         let web3 = self.web3.clone();
         let data = self.data.clone();
 
-        let addrs = self.data.get_proposals(); //获得所有提案ID
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_secs();
+        //滚动窗口前移到已检索的区块
+        let indexed = self
+            .from_block
+            .load(Ordering::Relaxed)
+            .saturating_sub(1)
+            .min(block);
+        match RollingVolume::advance(&data, &web3, indexed).await {
+            Ok(true) => {
+                if let Err(e) = Oracle::apply(&data).await {
+                    log::error!("apply usd price error: {:?}", e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => log::error!("advance rolling volume error: {:?}", e),
+        }
         if self.start.load(Ordering::Relaxed) + 60 * 60 < now {
-            Proposal::update_24h_hot(&data, &web3, addrs, block)
-                .await
-                .map_err(|e| e.to_string())?;
+            //Original状态的提案可能调用了setProposalInfo
+            let originals = data.get_proposals_by_state(ProposalState::Original);
+            Proposal::refresh_info(&data, &web3, originals).await;
+            self.refresh_leaderboard(now).await;
+            if let Err(e) = self.reconcile(indexed, block_step).await {
+                log::error!("reconcile proposals error: {:?}", e);
+            }
+            //abi中没有的事件,可能是合约升级后新增
+            for (source, topic, count) in self.registry.unknown() {
+                log::warn!("unknown topic {:?} from {:?} seen {} times", topic, source, count);
+            }
             self.start.swap(now, Ordering::Relaxed);
         }
-        RuntimeTime::sleep(Duration::from_secs(10)).await;
+        if self.oracle_refreshed.load(Ordering::Relaxed) + ORACLE_INTERVAL < now {
+            if let Err(e) = Oracle::refresh(&data, self.oracle.as_ref()).await {
+                log::error!("refresh usd price error: {:?}", e);
+            }
+            self.oracle_refreshed.swap(now, Ordering::Relaxed);
+        }
+        self.wait_next().await;
         Ok(())
     }
 
-    async fn log_update(&self, from_block: u64, to_block: u64, block: u64) -> BoxedResult<()> {
-        let web3 = self.web3.clone();
+    /*
+        等待下一次检索,最多等待10秒
+        订阅了websocket时收到新区块或日志立即返回
+    */
+    async fn wait_next(&self) {
+        let sleep = Box::pin(RuntimeTime::sleep(Duration::from_secs(10)));
+        let wake = match &self.wake {
+            Some(wake) => wake,
+            None => return sleep.await,
+        };
+        let mut wake = wake.lock().await;
+        if let Either::Right((None, sleep)) = select(sleep, wake.next()).await {
+            return sleep.await;
+        }
+        //检索前积压的通知只唤醒一次
+        while let Ok(Some(_)) = wake.try_next() {}
+    }
+
+    /*
+        工厂已分配的提案ID中有未入库的,说明有提案创建时处理失败
+        从第一个缺失ID之前最近入库提案的区块起,按学到的区间检索工厂和路由日志,只处理缺失提案的事件
+        由工厂在日志区块读取提案合约补齐,再补取补齐的提案和选项代币的日志,重算交易额和盈亏
+        单个步骤失败时记录错误继续,全部成功后记录已对账的提案ID,提案数不变时不再检索
+    */
+    async fn reconcile(&self, indexed: u64, block_step: u64) -> BoxedResult<()> {
         let data = self.data.clone();
-        let store = &data.store;
+        let current = Factory::currency_proposal_id(&self.web3, self.factory, indexed)
+            .await
+            .map_err(|e| e.to_string())?;
+        if current <= self.reconciled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let stored = data.store.read_proposal_blocks().await?;
+        let (missing, from_block) = Self::missing_proposals(&stored, current);
+        if missing.is_empty() {
+            self.reconciled.swap(current, Ordering::Relaxed);
+            return Ok(());
+        }
+        log::warn!(
+            "factory proposal id {}, missing {:?}, reconcile from {}",
+            current,
+            missing,
+            from_block
+        );
+        let mut failed = false;
+        let contracts = [self.factory, self.router];
+        let logs = match self
+            .fetch_range(from_block, indexed, &contracts, block_step)
+            .await
+        {
+            Ok(logs) => logs,
+            Err(e) => {
+                log::error!("reconcile {} to {} error: {:?}", from_block, indexed, e);
+                failed = true;
+                vec![]
+            }
+        };
+        for log in logs.iter() {
+            let module = match self.module_test.get(&log.address) {
+                Some(module) => module,
+                None => continue,
+            };
+            let event = match self.registry.decode(module.source(), log) {
+                Some(event) if event.proposal_id().is_some_and(|id| missing.contains(&id)) => event,
+                _ => continue,
+            };
+            if let Err(e) = module.with_fn(log, event).await {
+                log::error!("reconcile {:?} error: {:?}", log.transaction_hash, e);
+                failed = true;
+            }
+        }
+
+        //补齐的提案按地址补取日志,选项代币转账一并处理
+        let recovered = missing
+            .into_iter()
+            .filter(|&id| data.get_proposal_address(id).is_some())
+            .collect::<Vec<u64>>();
+        if !recovered.is_empty() {
+            log::info!("recovered proposals {:?}", recovered);
+            let mut addrs = recovered
+                .iter()
+                .filter_map(|&id| data.get_proposal_address(id))
+                .collect::<Vec<H160>>();
+            addrs.extend(data.get_outcome_tokens().into_iter().filter(|token| {
+                data.get_outcome_token(token)
+                    .is_some_and(|(id, _)| recovered.contains(&id))
+            }));
+            match self
+                .fetch_range(from_block, indexed, &addrs, block_step)
+                .await
+            {
+                Ok(logs) => {
+                    if let Err(e) = self.apply_logs(&logs, &mut vec![]).await {
+                        log::error!("reconcile {} to {} error: {:?}", from_block, indexed, e);
+                        failed = true;
+                    }
+                }
+                Err(e) => {
+                    log::error!("reconcile {} to {} error: {:?}", from_block, indexed, e);
+                    failed = true;
+                }
+            }
+            if let Err(e) = RollingVolume::rebuild(&data, &self.web3, indexed).await {
+                log::error!("reconcile rebuild rolling volume error: {:?}", e);
+                failed = true;
+            }
+            if let Err(e) = Oracle::apply(&data).await {
+                log::error!("reconcile apply usd price error: {:?}", e);
+                failed = true;
+            }
+            if let Err(e) = Pnl::replay(&data, recovered).await {
+                log::error!("reconcile replay pnl error: {:?}", e);
+                failed = true;
+            }
+        }
+        if !failed {
+            self.reconciled.swap(current, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /*
+        1..=current中没有入库的提案ID,以及重新检索的起始区块
+        起始区块为第一个缺失ID之前最近入库提案的区块,没有时从0开始
+    */
+    fn missing_proposals(stored: &[(u64, u64)], current: u64) -> (Vec<u64>, u64) {
+        let ids = stored.iter().map(|(id, _)| *id).collect::<HashSet<u64>>();
+        let missing = (1..=current)
+            .filter(|id| !ids.contains(id))
+            .collect::<Vec<u64>>();
+        let from_block = missing
+            .first()
+            .and_then(|first| {
+                stored
+                    .iter()
+                    .filter(|(id, _)| id < first)
+                    .map(|(_, block)| *block)
+                    .max()
+            })
+            .unwrap_or(0);
+        (missing, from_block)
+    }
+
+    /*
+        按时间窗口物化排行榜
+    */
+    async fn refresh_leaderboard(&self, now: u64) {
+        for window in LeaderboardWindow::WINDOWS {
+            if let Err(e) = self
+                .data
+                .store
+                .write_leaderboard(format!("{:?}", window), window.since(now), now)
+                .await
+            {
+                log::error!("write leaderboard {:?} error: {:?}", window, e);
+            }
+        }
+    }
+
+    /*
+        先取to_block的hash再取日志,日志期间发生回滚时下一次检测会发现不一致
+    */
+    async fn fetch_logs(
+        &self,
+        web3: &Web3<Failover>,
+        from_block: u64,
+        to_block: u64,
+        contracts: Vec<H160>,
+        topics: &[H256],
+    ) -> Result<(Option<Block<H256>>, Vec<Log>), Web3Error> {
+        let to = web3.eth().block(BlockId::Number(to_block.into())).await?;
+        let logs = LogFilter::logs(web3, from_block, to_block, &contracts, topics).await?;
+        Ok((to, logs))
+    }
+
+    //节点限制了单次返回的日志数量或区块范围,或请求超时,缩小区间可以重试
+    fn is_range_error(e: &Web3Error) -> bool {
+        match e {
+            Web3Error::Rpc(e) => {
+                let message = e.message.to_lowercase();
+                e.code.code() == RANGE_ERROR_CODE
+                    || RANGE_ERROR_MESSAGES.iter().any(|key| message.contains(key))
+            }
+            Web3Error::Transport(message) => {
+                let message = message.to_lowercase();
+                message.contains("timed out") || message.contains("timeout")
+            }
+            _ => false,
+        }
+    }
 
+    //按地址检索的工厂,路由和选项代币,提案合约按事件签名检索
+    fn contracts(&self) -> Vec<H160> {
         let mut contracts = self.module_test.keys().cloned().collect::<Vec<H160>>();
-        let addrs = self.data.get_proposals();
-        contracts.extend(addrs.iter());
-        let filter = FilterBuilder::default()
-            .from_block(from_block.into())
-            .to_block(to_block.into())
-            .address(contracts.clone())
-            .build();
+        contracts.extend(self.data.get_outcome_tokens().iter());
+        contracts
+    }
 
-        let logs = web3.eth().logs(filter).await?;
+    /*
+        检索[from_block, to_block]的日志,返回实际检索到的区块,日志和下一次检索的区块数
+        结果过多或超时时区间减半重试,结果少且快时区间加倍
+    */
+    async fn fetch_adaptive(
+        &self,
+        web3: &Web3<Failover>,
+        from_block: u64,
+        to_block: u64,
+        contracts: Vec<H160>,
+        block_step: u64,
+    ) -> BoxedResult<(u64, Block<H256>, Vec<Log>, u64)> {
+        let mut to_block = to_block;
+        let mut step = block_step;
+        loop {
+            let start = Instant::now();
+            match self
+                .fetch_logs(web3, from_block, to_block, contracts.clone(), &self.topics)
+                .await
+            {
+                Ok((to, logs)) => {
+                    let full = to_block - from_block + 1 >= step;
+                    if full && logs.len() < STEP_GROW_LOGS && start.elapsed() < STEP_FAST {
+                        step = (step * 2).min(MAX_STEP);
+                    }
+                    return Ok((to_block, to.ok_or("empty block")?, logs, step));
+                }
+                Err(e) if Self::is_range_error(&e) && to_block - from_block + 1 > MIN_STEP => {
+                    let blocks = to_block - from_block + 1;
+                    step = (blocks / 2).max(MIN_STEP);
+                    to_block = from_block + step - 1;
+                    log::warn!("get logs error: {:?}, retry to {} with step {}", e, to_block, step);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
 
-        let mut addrs = Vec::<H160>::new();
+    /*
+        分段检索[from_block, to_block]内指定地址的日志,用于补取历史区间
+        区间过大或超时时减半重试,结果少且快时加倍,与fetch_adaptive一致
+    */
+    async fn fetch_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        contracts: &[H160],
+        block_step: u64,
+    ) -> BoxedResult<Vec<Log>> {
+        let mut logs = Vec::new();
+        let mut from = from_block;
+        let mut step = block_step.clamp(MIN_STEP, MAX_STEP);
+        while from <= to_block {
+            let to = (from + step - 1).min(to_block);
+            let start = Instant::now();
+            match LogFilter::logs(&self.web3, from, to, contracts, &[]).await {
+                Ok(more) => {
+                    if more.len() < STEP_GROW_LOGS && start.elapsed() < STEP_FAST {
+                        step = (step * 2).min(MAX_STEP);
+                    }
+                    logs.extend(more);
+                    from = to + 1;
+                }
+                Err(e) if Self::is_range_error(&e) && to - from + 1 > MIN_STEP => {
+                    let blocks = to - from + 1;
+                    step = (blocks / 2).max(MIN_STEP);
+                    log::warn!("get logs {} to {} error: {:?}, step {}", from, to, e, step);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(logs)
+    }
+
+    /*
+        升级前的提案没有交易流水,从提案创建区块补取到to_block的Buy/Sell写入trades
+        每个提案补齐后记录标记,只补一次;滚动交易额由调用方之后按流水重算
+    */
+    async fn backfill_trades(&self, to_block: u64, block_step: u64) -> BoxedResult<()> {
+        let store = &self.data.store;
+        for (proposal_id, address, from_block) in store.read_proposals_without_trades().await? {
+            let address = address.parse::<H160>()?;
+            let logs = self
+                .fetch_range(from_block, to_block, &[address], block_step)
+                .await?;
+            for log in logs.iter() {
+                if let Some(event) = self.registry.decode(EventSource::Proposal, log) {
+                    Proposal::backfill_trade(&self.data, &self.web3, log, &event, proposal_id)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            store.write_trades_backfilled(proposal_id).await?;
+            log::info!("backfill proposal {} trades to {}", proposal_id, to_block);
+        }
+        Ok(())
+    }
+
+    /*
+        新登记的选项代币从提案创建区块补取到to_block的日志并写入持仓流水
+        流水按交易hash和日志序号去重,重复补取不会重复计入
+    */
+    async fn backfill_tokens(
+        &self,
+        tokens: Vec<H160>,
+        to_block: u64,
+        block_step: u64,
+    ) -> BoxedResult<()> {
+        let data = &self.data;
+        let mut proposal_ids = Vec::<u64>::new();
+        for token in tokens {
+            let proposal_id = match data.get_outcome_token(&token) {
+                Some((proposal_id, _)) => proposal_id,
+                None => continue,
+            };
+            let from_block = data
+                .store
+                .read_proposal_block(proposal_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .unwrap_or(0);
+            let logs = self
+                .fetch_range(from_block, to_block, &[token], block_step)
+                .await?;
+            self.apply_logs(&logs, &mut vec![]).await?;
+            proposal_ids.push(proposal_id);
+        }
+        proposal_ids.sort_unstable();
+        proposal_ids.dedup();
+        Pnl::replay(data, proposal_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /*
+        落后超过一个区间时,并发检索后续最多parallelism - 1个区间
+        某个区间失败时丢弃它和之后的区间,下一次tick重新检索
+    */
+    async fn fetch_backfill(
+        &self,
+        web3: &Web3<Failover>,
+        from_block: u64,
+        block: u64,
+        contracts: &[H160],
+        step: u64,
+    ) -> Vec<(u64, u64, Block<H256>, Vec<Log>)> {
+        let ranges = Self::backfill_ranges(from_block, block, step, self.parallelism);
+        let fetched =
+            join_all(ranges.iter().map(|(from, to)| {
+                self.fetch_logs(web3, *from, *to, contracts.to_vec(), &self.topics)
+            }))
+            .await;
+        let mut windows = Vec::new();
+        for ((from, to_block), res) in ranges.into_iter().zip(fetched) {
+            match res {
+                Ok((Some(to), logs)) => windows.push((from, to_block, to, logs)),
+                Ok((None, _)) => break,
+                Err(e) => {
+                    log::warn!("backfill {} to {} error: {:?}", from, to_block, e);
+                    break;
+                }
+            }
+        }
+        windows
+    }
+
+    //从from_block起按step切分到block,最多parallelism - 1个区间
+    fn backfill_ranges(
+        from_block: u64,
+        block: u64,
+        step: u64,
+        parallelism: usize,
+    ) -> Vec<(u64, u64)> {
+        (0..parallelism.saturating_sub(1) as u64)
+            .map(|i| from_block + i * step)
+            .take_while(|from| *from < block)
+            .map(|from| (from, (from + step - 1).min(block)))
+            .collect()
+    }
+
+    //处理提案合约的日志,返回有变化的提案地址
+    async fn apply_proposal_log(&self, log: &Log) -> BoxedResult<Option<H160>> {
+        let event = match self.registry.decode(EventSource::Proposal, log) {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+        Proposal::with_proposal(&self.data, &self.web3, log, event)
+            .await
+            .map_err(|e| format!("with proposal {:?} error: {}", log.transaction_hash, e))?;
+        Ok(Some(log.address))
+    }
+
+    /*
+        处理一个区间的日志,有变化的提案地址加入addrs
+        提案合约在创建交易中先于CreateProposal发出Initialize,暂缓到工厂登记该提案后处理
+        只处理工厂登记的地址,其他合约同签名的事件丢弃
+        提案日志写入流水失败时返回错误,调用方不记录该区间,下一次重新检索
+    */
+    async fn apply_logs(&self, logs: &[Log], addrs: &mut Vec<H160>) -> BoxedResult<()> {
+        let data = &self.data;
+        let mut deferred = Vec::<&Log>::new();
         for log in logs.iter() {
             //如果日志中 router和factory状态变化 则执行更新
             if let Some(without_data) = self.module_test.get(&log.address) {
-                if let Err(e) = without_data.with_fn(log).await {
-                    log::error!("test with {:?} error: {:?}", log.address, e);
+                if let Some(event) = self.registry.decode(without_data.source(), log) {
+                    if let Err(e) = without_data.with_fn(log, event).await {
+                        log::error!("test with {:?} error: {:?}", log.address, e);
+                    }
+                }
+                //工厂登记了新提案,处理暂缓的日志
+                let (ready, rest): (Vec<&Log>, Vec<&Log>) = deferred
+                    .into_iter()
+                    .partition(|deferred| data.contains_proposal(&deferred.address));
+                deferred = rest;
+                for log in ready {
+                    addrs.extend(self.apply_proposal_log(log).await?);
                 }
             }
             //如果日志中有提案状态变化 则执行更新
-            if data.contains_proposal(&log.address) {
-                if let Err(e) = Proposal::with_proposal(&self.data, &web3, log).await {
-                    log::error!("with proposal error: {:?}", e);
+            else if data.contains_proposal(&log.address) {
+                addrs.extend(self.apply_proposal_log(log).await?);
+            }
+            //选项代币转账 更新持仓流水
+            else if let Some((proposal_id, index)) = data.get_outcome_token(&log.address) {
+                match self.registry.decode(EventSource::OutcomeToken, log) {
+                    Some(Event::Transfer(transfer)) => {
+                        if let Err(e) =
+                            OutcomeToken::with_transfer(data, log, &transfer, proposal_id, index)
+                                .await
+                        {
+                            log::error!("with transfer error: {:?}", e);
+                        }
+                    }
+                    Some(Event::Burn(burn)) => {
+                        let transfers: Vec<_> = logs
+                            .iter()
+                            .filter_map(|other| {
+                                match self.registry.decode(EventSource::OutcomeToken, other) {
+                                    Some(Event::Transfer(transfer)) => Some((other, transfer)),
+                                    _ => None,
+                                }
+                            })
+                            .collect();
+                        if OutcomeToken::burn_transferred(&transfers, log, &burn) {
+                            continue;
+                        }
+                        if let Err(e) =
+                            OutcomeToken::with_burn(data, log, &burn, proposal_id, index).await
+                        {
+                            log::error!("with burn error: {:?}", e);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            //按事件签名检索到的未登记地址
+            else {
+                deferred.push(log);
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        区间处理完后在一个事务中记录检索区块,区块hash和时间戳,再前移from_block
+    */
+    async fn checkpoint(&self, to_block: u64, to: &Block<H256>, logs: &[Log]) -> BoxedResult<()> {
+        let mut hashes = logs
+            .iter()
+            .filter_map(|log| match (log.block_number, log.block_hash) {
+                (Some(number), Some(hash)) => Some((number.as_u64(), format!("{:?}", hash))),
+                _ => None,
+            })
+            .collect::<HashMap<u64, String>>();
+        let to_hash = to.hash.ok_or("empty block")?;
+        hashes.insert(to_block, format!("{:?}", to_hash));
+        self.data
+            .store
+            .write_checkpoint(
+                self.data.chain_id,
+                to_block,
+                to.timestamp.as_u64(),
+                hashes.into_iter().collect(),
+                to_block.saturating_sub(REORG_DEPTH),
+            )
+            .await?;
+        //提交成功后更新block下一次检索区块
+        self.from_block.swap(to_block + 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /*
+        检索[from_block, to_block]的日志,返回下一次检索的区块数,区块数变化后写入数据库
+        落后较多时并发检索后续区间,按区块顺序依次处理并记录检索进度
+    */
+    async fn log_update(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        block: u64,
+        block_step: u64,
+    ) -> BoxedResult<u64> {
+        let web3 = self.web3.clone();
+        let data = self.data.clone();
+        let store = &data.store;
+
+        //第一个区间自适应区块数,与后续区间同时检索
+        //区块和日志都从同一个节点读取,避免节点之间高度或分叉不一致
+        let pinned = Web3::new(data.rpc.pinned());
+        let contracts = self.contracts();
+        let (first, backfill) = join(
+            self.fetch_adaptive(&pinned, from_block, to_block, contracts.clone(), block_step),
+            self.fetch_backfill(&pinned, to_block + 1, block, &contracts, block_step),
+        )
+        .await;
+        let (fetched, to, logs, step) = first?;
+        let mut windows = vec![(from_block, fetched, to, logs)];
+        //第一个区间缩小后与后续区间不再连续,后续区间丢弃
+        if fetched == to_block {
+            windows.extend(backfill);
+        }
+
+        let mut known = contracts.into_iter().collect::<HashSet<H160>>();
+        let mut addrs = Vec::<H160>::new();
+        let mut applied = from_block.saturating_sub(1);
+        for (from, to_block, to, mut logs) in windows {
+            //前面区间新增的选项代币,补取本区间的日志
+            let added = self
+                .contracts()
+                .into_iter()
+                .filter(|addr| !known.contains(addr))
+                .collect::<Vec<H160>>();
+            if !added.is_empty() {
+                known.extend(added.iter());
+                match self.fetch_logs(&pinned, from, to_block, added, &[]).await {
+                    Ok((_, more)) => {
+                        logs.extend(more);
+                        logs.sort_by_key(|log| (log.block_number, log.log_index));
+                    }
+                    Err(e) => {
+                        log::warn!("backfill {} to {} error: {:?}", from, to_block, e);
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = self.apply_logs(&logs, &mut addrs).await {
+                log::error!("apply {} to {} error: {:?}", from, to_block, e);
+                break;
+            }
+            //本区间创建的提案新增的选项代币,从创建区块补取到本区间结束
+            let created = self
+                .contracts()
+                .into_iter()
+                .filter(|addr| !known.contains(addr))
+                .collect::<Vec<H160>>();
+            if !created.is_empty() {
+                if let Err(e) = self.backfill_tokens(created, to_block, step).await {
+                    log::error!("backfill tokens {} to {} error: {:?}", from, to_block, e);
+                    break;
                 }
-                addrs.push(log.address);
             }
+            if let Err(e) = self.checkpoint(to_block, &to, &logs).await {
+                log::error!("checkpoint {} to {} error: {:?}", from, to_block, e);
+                break;
+            }
+            applied = to_block;
+        }
+
+        //重算日志中产生变化的提案盈亏
+        let mut proposal_ids = addrs
+            .iter()
+            .filter_map(|addr| data.get_proposal_id(addr))
+            .collect::<Vec<u64>>();
+        proposal_ids.sort_unstable();
+        proposal_ids.dedup();
+        if let Err(e) = Pnl::replay(&data, proposal_ids).await {
+            log::error!("replay pnl error: {:?}", e);
         }
-        //更新日志中产生变化的提案hot
-        Proposal::update_24h_hot(&data, &web3, addrs, block)
+        //交易入库时已累加滚动窗口,窗口前移到已处理的区块后按缓存的美元价格重算
+        let moved = RollingVolume::advance(&data, &web3, applied)
             .await
             .map_err(|e| e.to_string())?;
+        if moved || !addrs.is_empty() {
+            if let Err(e) = Oracle::apply(&data).await {
+                log::error!("apply usd price error: {:?}", e);
+            }
+        }
 
-        //更新数据库的检索区块
-        let chain_id = self.data.chain_id;
-        if let Err(e) = store.write_block_hight(chain_id, to_block).await {
-            log::error!("write block hight error: {:?}", e);
+        if step != block_step {
+            log::info!("block step {} -> {}", block_step, step);
+            if let Err(e) = store.write_block_step(data.chain_id, step).await {
+                log::error!("write block step error: {:?}", e);
+            }
         }
-        //更新block下一次检索区块
-        self.from_block.swap(to_block + 1, Ordering::Relaxed);
-        Ok(())
+        Ok(step)
     }
 
     /*
@@ -169,8 +1023,44 @@ impl BlockActor {
         let chain_id = self.data.chain_id;
         let (from_block, block_step) = store.read_block(chain_id).await.unwrap_or((0, 100));
         self.from_block.swap(from_block, Ordering::Relaxed);
+        Factory::refresh_decimals(&self.data, &self.web3).await;
+        //按流水重算滚动交易额,窗口终点为已检索的区块
+        let indexed = from_block.saturating_sub(1);
+        if let Err(e) = self.backfill_trades(indexed, block_step).await {
+            log::error!("backfill trades error: {:?}", e);
+        }
+        if let Err(e) = RollingVolume::rebuild(&self.data, &self.web3, indexed).await {
+            log::error!("rebuild rolling volume error: {:?}", e);
+        }
+        if let Err(e) = Oracle::refresh(&self.data, self.oracle.as_ref()).await {
+            log::error!("refresh usd price error: {:?}", e);
+        }
+        //旧数据没有选项代币地址,从合约补齐,并补取补齐的代币已检索区间的转账
+        let known = self.data.get_outcome_tokens();
+        let proposals = self.data.get_proposals_without_tokens();
+        Proposal::refresh_info(&self.data, &self.web3, proposals).await;
+        let added = self
+            .data
+            .get_outcome_tokens()
+            .into_iter()
+            .filter(|token| !known.contains(token))
+            .collect::<Vec<H160>>();
+        if !added.is_empty() {
+            if let Err(e) = self.backfill_tokens(added, indexed, block_step).await {
+                log::error!("backfill outcome tokens error: {:?}", e);
+            }
+        }
+        //启动时从流水重算全部盈亏
+        if let Err(e) = Pnl::replay_all(&self.data).await {
+            log::error!("replay pnl error: {:?}", e);
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.refresh_leaderboard(now).await;
         // Proposal::update_total(&self.data, &self.web3, from_block).await;
-        self.send_ping(block_step);
+        self.send_ping(block_step.clamp(MIN_STEP, MAX_STEP));
     }
 
     /*
@@ -194,6 +1084,14 @@ impl Actor for BlockActor {
     fn started(&mut self, ctx: &mut Self::Context) {
         let add = ctx.address();
         self.rec_ping = Some(add.recipient());
+        //订阅任务独立运行,不受检索阻塞
+        if let Some(url) = self.ws.clone() {
+            let (tx, rx) = mpsc::unbounded();
+            self.wake = Some(Rc::new(Mutex::new(rx)));
+            let contracts = self.module_test.keys().cloned().collect::<Vec<H160>>();
+            let topics = self.topics.to_vec();
+            actix::spawn(Subscriber::new(url, contracts, topics, tx).run());
+        }
         let shadow_self = self.clone();
         super::async_call(
             self,
@@ -218,12 +1116,106 @@ impl Handler<Ping> for BlockActor {
             ctx,
             async move { shadow.tick(step).await },
             move |r, a, _| {
-                if let Err(e) = r {
+                let step = r.unwrap_or_else(|e| {
                     log::error!("handle ping error: {:?}", e);
-                }
+                    step
+                });
                 a.send_ping(step);
                 fut::ready(())
             },
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jsonrpc_core::{Error as RpcError, ErrorCode};
+    use web3::error::Error as Web3Error;
+
+    use super::BlockActor;
+
+    fn rpc(code: i64, message: &str) -> Web3Error {
+        Web3Error::Rpc(RpcError {
+            code: ErrorCode::ServerError(code),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn backfill_ranges_split_by_step() {
+        assert_eq!(
+            BlockActor::backfill_ranges(101, 350, 100, 4),
+            vec![(101, 200), (201, 300), (301, 350)]
+        );
+        //最多parallelism - 1个区间
+        assert_eq!(
+            BlockActor::backfill_ranges(101, 1000, 100, 3),
+            vec![(101, 200), (201, 300)]
+        );
+        assert_eq!(
+            BlockActor::backfill_ranges(101, 102, 100, 4),
+            vec![(101, 102)]
+        );
+        assert!(BlockActor::backfill_ranges(101, 101, 100, 4).is_empty());
+        assert!(BlockActor::backfill_ranges(101, 1000, 100, 1).is_empty());
+    }
+
+    #[test]
+    fn range_errors_by_code_and_message() {
+        assert!(BlockActor::is_range_error(&rpc(-32005, "limit exceeded")));
+        assert!(BlockActor::is_range_error(&rpc(
+            -32000,
+            "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"
+        )));
+        assert!(BlockActor::is_range_error(&rpc(
+            -32000,
+            "exceed maximum block range: 5000"
+        )));
+        assert!(BlockActor::is_range_error(&Web3Error::Transport(
+            "operation timed out".to_string()
+        )));
+    }
+
+    #[test]
+    fn other_errors_not_retried_by_range() {
+        //只含limit/range/exceed等词的错误不缩小区间
+        assert!(!BlockActor::is_range_error(&rpc(
+            -32000,
+            "rate limit reached"
+        )));
+        assert!(!BlockActor::is_range_error(&rpc(
+            -32000,
+            "insufficient funds: exceeds balance"
+        )));
+        assert!(!BlockActor::is_range_error(&rpc(
+            3,
+            "execution reverted: index out of range"
+        )));
+        assert!(!BlockActor::is_range_error(&Web3Error::Transport(
+            "connection refused".to_string()
+        )));
+        assert!(!BlockActor::is_range_error(&Web3Error::Unreachable));
+    }
+
+    #[test]
+    fn missing_proposals_in_gaps() {
+        //中间和末尾的缺失ID,从第一个缺失ID之前的提案区块检索
+        let stored = [(1, 100), (2, 150), (4, 300), (5, 320)];
+        let (missing, from_block) = BlockActor::missing_proposals(&stored, 7);
+        assert_eq!(missing, vec![3, 6, 7]);
+        assert_eq!(from_block, 150);
+
+        //没有缺失
+        let (missing, _) = BlockActor::missing_proposals(&stored[..2], 2);
+        assert!(missing.is_empty());
+
+        //第一个提案缺失时从0开始
+        let (missing, from_block) = BlockActor::missing_proposals(&stored[1..], 5);
+        assert_eq!(missing, vec![1, 3]);
+        assert_eq!(from_block, 0);
+        let (missing, from_block) = BlockActor::missing_proposals(&[], 2);
+        assert_eq!(missing, vec![1, 2]);
+        assert_eq!(from_block, 0);
+    }
+}
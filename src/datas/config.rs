@@ -5,14 +5,45 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u32,
     pub database_url: String,
+    //管理接口的token,不配置时管理接口不可用
+    #[serde(default)]
+    pub admin_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ContractConfig {
     pub factory: String,
     pub router: String,
+    //多个节点以逗号分隔
     pub rpc: String,
+    //落后最高区块超过该数量的节点不参与请求
+    #[serde(default = "ContractConfig::default_rpc_max_lag")]
+    pub rpc_max_lag: u64,
+    //websocket节点,配置后订阅新区块和日志,不配置时按http轮询
+    #[serde(default)]
+    pub ws: Option<String>,
     pub chain_id: u32,
+    #[serde(default)]
+    pub confirmations: u64,
+    //落后较多时并发检索日志的区间数,默认1即逐个区间检索
+    #[serde(default = "ContractConfig::default_backfill_parallelism")]
+    pub backfill_parallelism: usize,
+    //读取合约失败时解析交易输入作为补充,只适用于直接调用合约的交易
+    #[serde(default)]
+    pub calldata_fallback: bool,
+    //链上定价合约,不配置时使用管理员提交的价格
+    #[serde(default)]
+    pub oracle: Option<String>,
+}
+
+impl ContractConfig {
+    fn default_rpc_max_lag() -> u64 {
+        5
+    }
+
+    fn default_backfill_parallelism() -> usize {
+        1
+    }
 }
 
 #[derive(Serialize, Deserialize)]
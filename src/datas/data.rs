@@ -1,6 +1,6 @@
 use actix::Recipient;
 use serde::Serialize;
-use web3::types::H160;
+use web3::types::{H160, U256};
 
 use crate::actors::ws::{SubOp, SubOpCode, XWsSub};
 use crate::db::StoreDB;
@@ -14,16 +14,24 @@ use std::{
     },
 };
 
-use super::handle::ProposalState;
+use super::handle::{ProposalSort, ProposalState};
+use crate::xprotocol::{transport::Failover, volume::VolumeWindow};
 pub struct AppData {
     pub store: StoreDB,
     pub support_list: RwLock<HashMap<(String, String), bool>>,
     pub categories: RwLock<Vec<&'static str>>,
-    pub liquidity: RwLock<Vec<&'static str>>,
+    pub liquidity: RwLock<Vec<ProposalSort>>,
     current_block: AtomicU64,
     pub chain_id: u32,
     proposals: RwLock<HashMap<H160, u64>>,
     proposals_state: RwLock<HashMap<u64, ProposalState>>,
+    outcome_tokens: RwLock<HashMap<H160, (u64, usize)>>,
+    proposal_tokens: RwLock<HashMap<u64, String>>,
+    decimals: RwLock<HashMap<String, u8>>,
+    usd_prices: RwLock<HashMap<String, U256>>,
+    volume_windows: RwLock<HashMap<VolumeWindow, u64>>,
+    pub admin_token: Option<String>,
+    pub rpc: Failover,
     client_list: RwLock<HashMap<Recipient<WsMessage>, HashSet<XWsSub>>>,
 }
 
@@ -31,13 +39,29 @@ impl AppData {
     pub fn new(
         store: StoreDB,
         categories: Vec<&'static str>,
-        liquidity: Vec<&'static str>,
+        liquidity: Vec<ProposalSort>,
         chain_id: u32,
-        proposals: Vec<(u64, String, String)>,
+        proposals: Vec<(u64, String, String, String, String, String)>,
+        admin_token: Option<String>,
+        rpc: Failover,
     ) -> Self {
+        let outcome_tokens = proposals
+            .iter()
+            .flat_map(|(id, _, _, _, token0, token1)| {
+                [token0, token1]
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(move |(i, v)| {
+                        v.parse::<H160>()
+                            .ok()
+                            .filter(|a| !a.is_zero())
+                            .map(|a| (a, (*id, i)))
+                    })
+            })
+            .collect::<HashMap<H160, (u64, usize)>>();
         let proposals_state = proposals
             .iter()
-            .map(|(id, _, state)| {
+            .filter_map(|(id, _, state, _, _, _)| {
                 let state = format!("{:?}", state).to_lowercase();
                 if let Ok(state) = serde_json::from_str(state.as_str()) {
                     Some((*id, state))
@@ -45,18 +69,20 @@ impl AppData {
                     None
                 }
             })
-            .flatten()
             .collect::<HashMap<u64, ProposalState>>();
+        let proposal_tokens = proposals
+            .iter()
+            .map(|(id, _, _, token, _, _)| (*id, token.clone()))
+            .collect::<HashMap<u64, String>>();
         let proposals = proposals
             .iter()
-            .map(|(id, v, _)| {
+            .filter_map(|(id, v, _, _, _, _)| {
                 if let Ok(a) = v.parse::<H160>() {
                     Some((a, *id))
                 } else {
                     None
                 }
             })
-            .flatten()
             .collect::<HashMap<H160, u64>>();
         Self {
             store,
@@ -67,13 +93,89 @@ impl AppData {
             chain_id,
             proposals: RwLock::new(proposals),
             proposals_state: RwLock::new(proposals_state),
+            outcome_tokens: RwLock::new(outcome_tokens),
+            proposal_tokens: RwLock::new(proposal_tokens),
+            decimals: RwLock::new(HashMap::new()),
+            usd_prices: RwLock::new(HashMap::new()),
+            volume_windows: RwLock::new(HashMap::new()),
+            admin_token,
+            rpc,
             client_list: RwLock::new(HashMap::new()),
         }
     }
 
-    pub fn insert_support(&self, addr: String, symbol: String, flag: bool) -> BoxedResult<()> {
+    pub fn insert_support(
+        &self,
+        addr: String,
+        symbol: String,
+        decimals: u8,
+        flag: bool,
+    ) -> BoxedResult<()> {
         let mut support_list = self.support_list.write().map_err(|e| e.to_string())?;
-        support_list.insert((addr, symbol), flag);
+        support_list.insert((addr.clone(), symbol), flag);
+        self.insert_decimals(addr, decimals)
+    }
+
+    pub fn insert_decimals(&self, addr: String, decimals: u8) -> BoxedResult<()> {
+        let mut list = self.decimals.write().map_err(|e| e.to_string())?;
+        list.insert(addr, decimals);
+        Ok(())
+    }
+
+    //币种精度,未知时按18位
+    pub fn get_decimals(&self, addr: &str) -> u8 {
+        if let Ok(list) = self.decimals.read() {
+            list.get(addr).copied().unwrap_or(18)
+        } else {
+            18
+        }
+    }
+
+    //提案结算币种的精度,选项代币和LP份额与结算币种精度一致
+    pub fn get_proposal_decimals(&self, proposal_id: u64) -> u8 {
+        let token = if let Ok(tokens) = self.proposal_tokens.read() {
+            tokens.get(&proposal_id).cloned()
+        } else {
+            None
+        };
+        token.map(|token| self.get_decimals(&token)).unwrap_or(18)
+    }
+
+    pub fn get_coins(&self) -> Vec<String> {
+        if let Ok(list) = self.decimals.read() {
+            list.keys().cloned().collect()
+        } else {
+            vec![]
+        }
+    }
+
+    //币种的美元价格,按USD_DECIMALS放大
+    pub fn insert_usd_price(&self, addr: String, price: U256) -> BoxedResult<()> {
+        let mut list = self.usd_prices.write().map_err(|e| e.to_string())?;
+        list.insert(addr, price);
+        Ok(())
+    }
+
+    pub fn get_usd_prices(&self) -> Vec<(String, U256)> {
+        if let Ok(list) = self.usd_prices.read() {
+            list.iter().map(|(addr, price)| (addr.clone(), *price)).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    //滚动窗口当前的起点区块,未初始化时为None
+    pub fn get_window_start(&self, window: VolumeWindow) -> Option<u64> {
+        if let Ok(list) = self.volume_windows.read() {
+            list.get(&window).copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn set_window_start(&self, window: VolumeWindow, block: u64) -> BoxedResult<()> {
+        let mut list = self.volume_windows.write().map_err(|e| e.to_string())?;
+        list.insert(window, block);
         Ok(())
     }
 
@@ -105,6 +207,27 @@ impl AppData {
         }
     }
 
+    pub fn get_proposals_by_state(&self, state: ProposalState) -> Vec<H160> {
+        let ids = if let Ok(proposals_state) = self.proposals_state.read() {
+            proposals_state
+                .iter()
+                .filter(|(_, &v)| v == state)
+                .map(|(&id, _)| id)
+                .collect::<HashSet<u64>>()
+        } else {
+            return vec![];
+        };
+        if let Ok(proposals) = self.proposals.read() {
+            proposals
+                .iter()
+                .filter(|(_, id)| ids.contains(id))
+                .map(|(&addr, _)| addr)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
     pub fn get_proposal_id(&self, proposal: &H160) -> Option<u64> {
         if let Ok(proposals) = self.proposals.read() {
             proposals.get(proposal).copied()
@@ -121,16 +244,85 @@ impl AppData {
         }
     }
 
-    pub fn set_proposal_state(&self, id: u64, state: ProposalState) {
-        if let Ok(mut proposals_state) = self.proposals_state.write() {
-            let _old_state = proposals_state.insert(id, state);
-        }
+    //返回修改前的状态
+    pub fn set_proposal_state(&self, id: u64, state: ProposalState) -> Option<ProposalState> {
+        self.proposals_state
+            .write()
+            .ok()
+            .and_then(|mut proposals_state| proposals_state.insert(id, state))
     }
 
-    pub fn insert_proposal(&self, proposal: H160, proposal_id: u64) {
+    pub fn insert_proposal(&self, proposal: H160, proposal_id: u64, token: String) {
         if let Ok(mut contracts) = self.proposals.write() {
             contracts.insert(proposal, proposal_id);
         }
+        if let Ok(mut tokens) = self.proposal_tokens.write() {
+            tokens.insert(proposal_id, token);
+        }
+    }
+
+    pub fn remove_proposal(&self, proposal: &H160) {
+        if let Ok(mut contracts) = self.proposals.write() {
+            if let Some(proposal_id) = contracts.remove(proposal) {
+                if let Ok(mut proposals_state) = self.proposals_state.write() {
+                    proposals_state.remove(&proposal_id);
+                }
+                if let Ok(mut outcome_tokens) = self.outcome_tokens.write() {
+                    outcome_tokens.retain(|_, (id, _)| *id != proposal_id);
+                }
+                if let Ok(mut tokens) = self.proposal_tokens.write() {
+                    tokens.remove(&proposal_id);
+                }
+            }
+        }
+    }
+
+    pub fn insert_outcome_tokens(&self, proposal_id: u64, tokens: [H160; 2]) {
+        if let Ok(mut outcome_tokens) = self.outcome_tokens.write() {
+            for (i, token) in tokens.into_iter().enumerate() {
+                if !token.is_zero() {
+                    outcome_tokens.insert(token, (proposal_id, i));
+                }
+            }
+        }
+    }
+
+    //选项代币对应的(提案ID, 选项序号)
+    pub fn get_outcome_token(&self, token: &H160) -> Option<(u64, usize)> {
+        if let Ok(outcome_tokens) = self.outcome_tokens.read() {
+            outcome_tokens.get(token).copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn get_outcome_tokens(&self) -> Vec<H160> {
+        if let Ok(outcome_tokens) = self.outcome_tokens.read() {
+            outcome_tokens.keys().copied().collect()
+        } else {
+            vec![]
+        }
+    }
+
+    //还没有记录选项代币的提案
+    pub fn get_proposals_without_tokens(&self) -> Vec<H160> {
+        let ids = if let Ok(outcome_tokens) = self.outcome_tokens.read() {
+            outcome_tokens
+                .values()
+                .map(|(id, _)| *id)
+                .collect::<HashSet<u64>>()
+        } else {
+            return vec![];
+        };
+        if let Ok(proposals) = self.proposals.read() {
+            proposals
+                .iter()
+                .filter(|(_, id)| !ids.contains(id))
+                .map(|(&addr, _)| addr)
+                .collect()
+        } else {
+            vec![]
+        }
     }
 
     pub async fn banners(&self) -> BoxedResult<Vec<String>> {
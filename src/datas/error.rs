@@ -27,6 +27,8 @@ pub enum XProtocolError {
     NotFound,
     #[error("bad requests")]
     BadRequest,
+    #[error("Unauthorized")]
+    Unauthorized,
     #[error("Method Not Allowed")]
     MethodNotAllowed,
     #[error("Expectation Failed")]
@@ -55,6 +57,7 @@ impl ResponseError for XProtocolError {
             Self::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
             Self::NotFound => StatusCode::NOT_FOUND,
             Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
             Self::ExpectationFailed => StatusCode::EXPECTATION_FAILED,
             Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,10 +1,17 @@
-use std::{collections::HashMap, str::FromStr, time::SystemTime};
+use std::{cmp::Reverse, collections::HashMap, str::FromStr, time::SystemTime};
 
 use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use sea_orm::FromQueryResult;
 use serde::{Deserialize, Serialize};
+use web3::types::U256;
 
-use crate::{actors::ws::XProtocolWs, db::StoreDB};
+use crate::{
+    actors::ws::XProtocolWs,
+    db::StoreDB,
+    entity::{pnl, trades},
+    xprotocol::{oracle::USD_DECIMALS, pnl::Pnl, ProposalResolution},
+};
 
 use super::{data::AppData, error::XProtocolError};
 
@@ -29,14 +36,40 @@ impl<T> XResponse<T> {
 pub struct Coins {
     pub address: String,
     pub symbol: String,
+    pub decimals: u8,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+//金额,raw为链上最小单位的整数,value为按精度换算后的小数
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
-pub struct History {
-    pub ts: u64,
-    pub t1: Vec<String>,
-    pub t2: Vec<String>
+pub struct Amount {
+    pub raw: String,
+    pub value: String,
+}
+
+impl Amount {
+    //raw可带负号,value去掉末尾多余的0
+    pub fn new(raw: String, decimals: u8) -> Self {
+        let (sign, digits) = match raw.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", raw.as_str()),
+        };
+        let amount = U256::from_dec_str(digits).unwrap_or_default();
+        let scale = U256::exp10(decimals as usize);
+        let sign = if amount.is_zero() { "" } else { sign };
+        let fraction = format!(
+            "{:0>width$}",
+            (amount % scale).to_string(),
+            width = decimals as usize
+        );
+        let fraction = fraction.trim_end_matches('0');
+        let value = if fraction.is_empty() {
+            format!("{}{}", sign, amount / scale)
+        } else {
+            format!("{}{}.{}", sign, amount / scale, fraction)
+        };
+        Self { raw, value }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
@@ -55,6 +88,116 @@ pub enum ProposalAduitState {
     NotPassed,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum CandleResolution {
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl CandleResolution {
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Self::FiveMinutes => 300,
+            Self::Hour => 3600,
+            Self::FourHours => 4 * 3600,
+            Self::Day => 86400,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardKind {
+    Traders,
+    Creators,
+    Lps,
+}
+
+impl LeaderboardKind {
+    //可排序的字段,第一个为默认排序
+    pub fn sorts(&self) -> &'static [&'static str] {
+        match self {
+            Self::Traders => &["volume", "pnl"],
+            Self::Creators => &["proposals", "volume"],
+            Self::Lps => &["liquidity", "estimated_fees"],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum LeaderboardWindow {
+    #[serde(rename = "24h")]
+    Day,
+    #[serde(rename = "7d")]
+    Week,
+    #[serde(rename = "30d")]
+    Month,
+    #[serde(rename = "all")]
+    All,
+}
+
+impl LeaderboardWindow {
+    pub const WINDOWS: [Self; 4] = [Self::Day, Self::Week, Self::Month, Self::All];
+
+    //窗口起点,全部时间为0
+    pub fn since(&self, now: u64) -> u64 {
+        match self {
+            Self::Day => now.saturating_sub(86400),
+            Self::Week => now.saturating_sub(7 * 86400),
+            Self::Month => now.saturating_sub(30 * 86400),
+            Self::All => 0,
+        }
+    }
+}
+
+/*
+    提案列表的排序方式,/categories/liquidity按顺序返回名称,
+    列表接口的liquidity参数为其序号
+*/
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum ProposalSort {
+    #[serde(rename = "24h volume")]
+    Volume24,
+    #[serde(rename = "48h volume")]
+    Volume48,
+    #[serde(rename = "Total volume")]
+    TotalVolume,
+    #[serde(rename = "Newest")]
+    Newest,
+    #[serde(rename = "Closing soon")]
+    ClosingSoon,
+}
+
+impl ProposalSort {
+    pub const SORTS: [Self; 5] = [
+        Self::Volume24,
+        Self::Volume48,
+        Self::TotalVolume,
+        Self::Newest,
+        Self::ClosingSoon,
+    ];
+
+    /*
+        排序字段和是否升序,交易额按美元比较,不同结算币种之间可排序
+        没有美元价格时美元交易额为0,再按精度换算后的原始交易额排序
+    */
+    pub fn order(&self) -> &'static [(&'static str, bool)] {
+        match self {
+            Self::Volume24 => &[("volume24_usd", false), ("volume24", false)],
+            Self::Volume48 => &[("volume48_usd", false), ("volume48", false)],
+            Self::TotalVolume => &[("volume_usd", false), ("volume", false)],
+            Self::Newest => &[("proposal_id", false)],
+            Self::ClosingSoon => &[("close_time", true)],
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ProposalRelation {
     Liquidity,
@@ -75,12 +218,44 @@ impl TryFrom<u32> for ProposalRelation {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LiquiditySide {
+    Add,
+    Remove,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Pagination {
     pub page: usize,
     pub count: usize,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CandleQuery {
+    pub resolution: CandleResolution,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenPriceReq {
+    pub token: String,
+    //1个币种对应的美元数,十进制小数
+    pub price: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LeaderboardQuery {
+    pub window: Option<LeaderboardWindow>,
+    pub sort: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ProposalItem {
@@ -89,6 +264,193 @@ pub struct ProposalItem {
     pub address: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeItem {
+    pub proposal_id: u64,
+    pub block: u64,
+    pub transaction_hash: String,
+    pub log_index: u32,
+    pub account: String,
+    pub token: String,
+    pub side: String,
+    pub amount: Amount,
+    pub ts: u64,
+}
+
+impl TradeItem {
+    pub fn new(trade: trades::Model, decimals: u8) -> Self {
+        Self {
+            proposal_id: trade.proposal_id,
+            block: trade.block,
+            transaction_hash: trade.transaction_hash,
+            log_index: trade.log_index,
+            account: trade.account,
+            token: trade.token,
+            side: trade.side,
+            amount: Amount::new(trade.amount, decimals),
+            ts: trade.ts,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalDetail {
+    pub proposal_id: u64,
+    pub address: String,
+    pub token: String,
+    pub token0: String,
+    pub token1: String,
+    pub title: String,
+    pub details: String,
+    pub outcome0: String,
+    pub outcome1: String,
+    pub fee_ratio: String,
+    pub category: u64,
+    pub create_time: u64,
+    pub close_time: u64,
+    pub audit_state: String,
+    pub state: String,
+    pub creater: String,
+    #[serde(skip_serializing)]
+    pub initial_liquidity: String,
+    #[serde(skip_serializing)]
+    pub liquidity: String,
+    #[serde(skip_serializing)]
+    pub volume: String,
+    #[serde(skip_serializing)]
+    pub volume24: String,
+    #[serde(skip_serializing)]
+    pub volume48: String,
+    #[serde(skip_serializing)]
+    pub volume7d: String,
+    #[serde(skip_serializing)]
+    pub volume_usd: String,
+    #[serde(skip_serializing)]
+    pub volume24_usd: String,
+    #[serde(skip_serializing)]
+    pub volume48_usd: String,
+    #[serde(skip_serializing)]
+    pub volume7d_usd: String,
+    #[serde(skip_serializing)]
+    pub tvl_usd: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalDetailRes {
+    #[serde(flatten)]
+    pub detail: ProposalDetail,
+    pub decimals: u8,
+    pub initial_liquidity: Amount,
+    pub liquidity: Amount,
+    pub volume: Amount,
+    pub volume24: Amount,
+    pub volume48: Amount,
+    pub volume7d: Amount,
+    pub volume_usd: Amount,
+    pub volume24_usd: Amount,
+    pub volume48_usd: Amount,
+    pub volume7d_usd: Amount,
+    pub tvl_usd: Amount,
+    pub winning_outcome: Option<String>,
+    pub resolution: Option<ProposalResolution>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderItem {
+    pub account: String,
+    pub shares: Amount,
+    pub added: Amount,
+    pub removed: Amount,
+    pub share: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioItem {
+    pub proposal_id: u64,
+    pub decimals: u8,
+    pub balances: [Amount; 2],
+    pub lp_shares: Amount,
+    pub bought: Amount,
+    pub sold: Amount,
+    pub price: [String; 2],
+    pub value: Amount,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PnlItem {
+    pub proposal_id: u64,
+    pub account: String,
+    pub decimals: u8,
+    pub quantity: [Amount; 2],
+    pub cost: [Amount; 2],
+    pub bought: Amount,
+    pub sold: Amount,
+    pub realized: Amount,
+    pub unrealized: Amount,
+    pub payout: Option<Amount>,
+    pub total: Amount,
+}
+
+//跨提案汇总时统一换算为NORMALIZED_DECIMALS位精度
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPnl {
+    pub realized: Amount,
+    pub unrealized: Amount,
+    pub payout: Amount,
+    pub total: Amount,
+    pub list: Vec<PnlItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Ohlc {
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Candle {
+    pub ts: u64,
+    pub outcome0: Ohlc,
+    pub outcome1: Ohlc,
+    pub volume: Amount,
+}
+
+//排行榜表中的金额已统一换算为NORMALIZED_DECIMALS位精度
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct LeaderboardRow {
+    pub account: String,
+    pub volume: String,
+    pub pnl: String,
+    pub proposals: u64,
+    pub liquidity: String,
+    pub estimated_fees: String,
+    pub updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardItem {
+    pub rank: u64,
+    pub account: String,
+    pub volume: Amount,
+    pub pnl: Amount,
+    pub proposals: u64,
+    pub liquidity: Amount,
+    pub estimated_fees: Amount,
+    pub updated_at: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ProposalList<T> {
@@ -113,6 +475,16 @@ impl ErrorResponse {
     }
 }
 
+const RATIO_DECIMALS: usize = 6;
+// 价格的小数位数
+pub const PRICE_DECIMALS: usize = 18;
+// 不同结算币种的金额汇总或比较时统一的精度
+pub const NORMALIZED_DECIMALS: u8 = 18;
+// 币种精度上限,10^77是U256能表示的最大10的幂
+pub const MAX_DECIMALS: u8 = 77;
+// 单次请求最多返回的K线数量
+const MAX_CANDLES: u64 = 1000;
+
 pub type CombineMap = HashMap<&'static str, HashMap<String, String>>;
 pub struct Handlers;
 
@@ -125,7 +497,30 @@ impl Handlers {
             .route("/formal/{status}", web::get().to(Self::select_proposal))
             .route("/banners", web::get().to(Self::banner))
             .route("/backstage/{token}", web::get().to(Self::backstage))
-            .route("/history/{id}", web::get().to(Self::history));
+            .route("/history/{id}", web::get().to(Self::history))
+            .route("/proposal/{id}", web::get().to(Self::proposal_detail))
+            .route("/trades/{proposal_id}", web::get().to(Self::trades))
+            .route(
+                "/account/{address}/trades",
+                web::get().to(Self::account_trades),
+            )
+            .route(
+                "/account/{address}/portfolio",
+                web::get().to(Self::portfolio),
+            )
+            .route(
+                "/account/{address}/pnl",
+                web::get().to(Self::account_pnl),
+            )
+            .route("/pnl/{proposal_id}", web::get().to(Self::proposal_pnl))
+            .route("/leaderboard/{kind}", web::get().to(Self::leaderboard))
+            .route("/candles/{proposal_id}", web::get().to(Self::candles))
+            .route("/admin/prices", web::post().to(Self::post_price))
+            .route("/admin/rpc", web::get().to(Self::rpc_status))
+            .route(
+                "/liquidity/{proposal_id}/providers",
+                web::get().to(Self::liquidity_providers),
+            );
         //ws
     }
 
@@ -151,31 +546,32 @@ impl Handlers {
             } else {
                 let price = history_price
                     .iter()
-                    .map(|(ts, a, _)| {
-                        (((ts - start_date) / 86400 + 1), *a as u128, 0)
+                    .map(|(ts, b0, b1)| {
+                        (((ts - start_date) / 86400 + 1), Self::price_ratio(b0, b1)[0], 0)
                     })
                     .fold(
-                        HashMap::<u64, (u128, u128)>::new(),
+                        HashMap::<u64, (U256, U256)>::new(),
                         |mut acc, (ts, a, _)| {
                             acc.entry(ts)
                                 .and_modify(|v| {
                                     v.0 += a;
-                                    v.1 += 1;
+                                    v.1 += U256::one();
                                 })
-                                .or_insert((a, 1));
+                                .or_insert((a, U256::one()));
                             acc
                         },
                     )
                     .iter()
                     .map(|(&k, &v)| (k, (v.0 / v.1)))
-                    .collect::<HashMap<u64, u128>>();
-                    let mut prev0 = "0.50".to_string();
-                    let mut prev1 = "0.50".to_string();
+                    .collect::<HashMap<u64, U256>>();
+                let scale = U256::exp10(PRICE_DECIMALS);
+                    let mut prev0 = Self::format_decimal(scale / 2, PRICE_DECIMALS);
+                    let mut prev1 = prev0.clone();
                 let x :Vec<u64>= (1..31).collect();
                 let x = x.iter().map(|&i|{
                     if let Some(&v) = price.get(&i){
-                        prev0 = format!("0.{}", v);
-                        prev1 = format!("0.{}", 100 - v);
+                        prev0 = Self::format_decimal(v, PRICE_DECIMALS);
+                        prev1 = Self::format_decimal(scale - v, PRICE_DECIMALS);
                     }
                     (i, (prev0.clone(), prev1.clone()))
                 }).fold(Vec::<(u64,String,String)>::new(),|mut acc,(t,(p1,p2))|{
@@ -189,6 +585,548 @@ impl Handlers {
         }
     }
 
+    /*
+        按resolution分桶的两个选项OHLC和交易额
+        区间内没有价格的桶沿用上一个收盘价
+    */
+    pub async fn candles(
+        data: web::Data<AppData>,
+        path: web::Path<u64>,
+        query: web::Query<CandleQuery>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let proposal_id = path.into_inner();
+        let decimals = data.get_proposal_decimals(proposal_id);
+        let CandleQuery {
+            resolution,
+            from,
+            to,
+        } = query.into_inner();
+        let step = resolution.seconds();
+        let (from, to) = Self::candle_range(from, to.unwrap_or_else(Self::now), step)
+            .ok_or(XProtocolError::BadRequest)?;
+
+        let store = &data.store;
+        let prices = store
+            .read_prices(proposal_id, from, to)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let volumes = store
+            .read_trade_volumes(proposal_id, from, to, step)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .into_iter()
+            .collect::<HashMap<u64, String>>();
+        //区间之前没有价格时按初始的50:50
+        let close = store
+            .read_price_before(proposal_id, from)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .map(|(b0, b1)| Self::price_ratio(&b0, &b1))
+            .unwrap_or_else(|| Self::price_ratio("1", "1"));
+
+        let prices = prices
+            .iter()
+            .map(|(ts, b0, b1)| (*ts, Self::price_ratio(b0, b1)));
+        let list = Self::bucket_candles(from, to, step, close, prices)
+            .into_iter()
+            .map(|(ts, ohlc)| {
+                let [outcome0, outcome1] = ohlc.map(|[open, high, low, close]| Ohlc {
+                    open: Self::format_decimal(open, PRICE_DECIMALS),
+                    high: Self::format_decimal(high, PRICE_DECIMALS),
+                    low: Self::format_decimal(low, PRICE_DECIMALS),
+                    close: Self::format_decimal(close, PRICE_DECIMALS),
+                });
+                Candle {
+                    ts,
+                    outcome0,
+                    outcome1,
+                    volume: Amount::new(
+                        volumes.get(&ts).cloned().unwrap_or_else(|| "0".to_string()),
+                        decimals,
+                    ),
+                }
+            })
+            .collect::<Vec<Candle>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, list)))
+    }
+
+    /*
+        对齐到桶的起点,to所在的桶包含在内
+        区间为空,超过MAX_CANDLES个桶或to对齐后溢出时返回None
+    */
+    fn candle_range(from: Option<u64>, to: u64, step: u64) -> Option<(u64, u64)> {
+        let from = from.unwrap_or_else(|| to.saturating_sub(step.saturating_mul(MAX_CANDLES - 1)));
+        let (from, to) = (from / step * step, (to / step * step).checked_add(step)?);
+        if from >= to || (to - from) / step > MAX_CANDLES {
+            return None;
+        }
+        Some((from, to))
+    }
+
+    //prices按时间升序,每个桶为两个选项的[open, high, low, close]
+    fn bucket_candles(
+        from: u64,
+        to: u64,
+        step: u64,
+        mut close: [U256; 2],
+        prices: impl Iterator<Item = (u64, [U256; 2])>,
+    ) -> Vec<(u64, [[U256; 4]; 2])> {
+        let mut prices = prices.peekable();
+        let mut list = Vec::with_capacity(((to - from) / step) as usize);
+        for ts in (from..to).step_by(step as usize) {
+            let mut ohlc = [[close[0]; 4], [close[1]; 4]];
+            while let Some((_, price)) = prices.next_if(|p| p.0 < ts + step) {
+                for (o, price) in ohlc.iter_mut().zip(price) {
+                    o[1] = o[1].max(price);
+                    o[2] = o[2].min(price);
+                    o[3] = price;
+                }
+                close = price;
+            }
+            list.push((ts, ohlc));
+        }
+        list
+    }
+
+    pub async fn proposal_detail(
+        data: web::Data<AppData>,
+        path: web::Path<u64>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let proposal_id = path.into_inner();
+        let detail = data
+            .store
+            .read_proposal_detail(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .ok_or(XProtocolError::NotFound)?;
+        let resolution = data
+            .store
+            .read_resolution(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .map(ProposalResolution::from);
+        //结果为胜出选项的序号
+        let winning_outcome = resolution.as_ref().and_then(|r| match r.result {
+            0 => Some(detail.outcome0.clone()),
+            1 => Some(detail.outcome1.clone()),
+            _ => None,
+        });
+        let decimals = data.get_proposal_decimals(proposal_id);
+        Ok(HttpResponse::Ok().json(XResponse::new(
+            StatusCode::OK,
+            ProposalDetailRes {
+                decimals,
+                initial_liquidity: Amount::new(detail.initial_liquidity.clone(), decimals),
+                liquidity: Amount::new(detail.liquidity.clone(), decimals),
+                volume: Amount::new(detail.volume.clone(), decimals),
+                volume24: Amount::new(detail.volume24.clone(), decimals),
+                volume48: Amount::new(detail.volume48.clone(), decimals),
+                volume7d: Amount::new(detail.volume7d.clone(), decimals),
+                volume_usd: Amount::new(detail.volume_usd.clone(), USD_DECIMALS),
+                volume24_usd: Amount::new(detail.volume24_usd.clone(), USD_DECIMALS),
+                volume48_usd: Amount::new(detail.volume48_usd.clone(), USD_DECIMALS),
+                volume7d_usd: Amount::new(detail.volume7d_usd.clone(), USD_DECIMALS),
+                tvl_usd: Amount::new(detail.tvl_usd.clone(), USD_DECIMALS),
+                detail,
+                winning_outcome,
+                resolution,
+            },
+        )))
+    }
+
+    pub async fn trades(
+        data: web::Data<AppData>,
+        path: web::Path<u64>,
+        info: web::Query<Pagination>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let proposal_id = path.into_inner();
+        let Pagination { page, count } = Self::check_pagination(info)?;
+        let (total, list) = data
+            .store
+            .read_trades(count, page, Some(proposal_id), None)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let list = list
+            .into_iter()
+            .map(|trade| {
+                let decimals = data.get_proposal_decimals(trade.proposal_id);
+                TradeItem::new(trade, decimals)
+            })
+            .collect::<Vec<TradeItem>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(
+            StatusCode::OK,
+            ProposalList {
+                current: page,
+                total,
+                list,
+            },
+        )))
+    }
+
+    pub async fn account_trades(
+        data: web::Data<AppData>,
+        path: web::Path<String>,
+        info: web::Query<Pagination>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let account = path.into_inner().to_lowercase();
+        let Pagination { page, count } = Self::check_pagination(info)?;
+        let (total, list) = data
+            .store
+            .read_trades(count, page, None, Some(account))
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let list = list
+            .into_iter()
+            .map(|trade| {
+                let decimals = data.get_proposal_decimals(trade.proposal_id);
+                TradeItem::new(trade, decimals)
+            })
+            .collect::<Vec<TradeItem>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(
+            StatusCode::OK,
+            ProposalList {
+                current: page,
+                total,
+                list,
+            },
+        )))
+    }
+
+    pub async fn liquidity_providers(
+        data: web::Data<AppData>,
+        path: web::Path<u64>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let proposal_id = path.into_inner();
+        let decimals = data.get_proposal_decimals(proposal_id);
+        let store = &data.store;
+        let total_supply = store
+            .read_proposal_liquidity(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::NotFound)?;
+        let total_supply = U256::from_dec_str(&total_supply).unwrap_or_default();
+        let events = store
+            .read_liquidity_events(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        //按区块顺序累计,shares取账户最后一次的份额
+        let providers = events.iter().fold(
+            HashMap::<String, (U256, U256, U256)>::new(),
+            |mut acc, event| {
+                let amount = U256::from_dec_str(&event.amount).unwrap_or_default();
+                let shares = U256::from_dec_str(&event.shares).unwrap_or_default();
+                let entry = acc.entry(event.account.clone()).or_default();
+                entry.0 = shares;
+                match event.side.as_str() {
+                    "Add" => entry.1 = entry.1.saturating_add(amount),
+                    _ => entry.2 = entry.2.saturating_add(amount),
+                }
+                acc
+            },
+        );
+        let mut list = providers
+            .into_iter()
+            .filter(|(_, (shares, _, _))| !shares.is_zero())
+            .collect::<Vec<(String, (U256, U256, U256))>>();
+        list.sort_by_key(|(_, (shares, _, _))| Reverse(*shares));
+        let list = list
+            .into_iter()
+            .map(|(account, (shares, added, removed))| ProviderItem {
+                account,
+                shares: Amount::new(shares.to_string(), decimals),
+                added: Amount::new(added.to_string(), decimals),
+                removed: Amount::new(removed.to_string(), decimals),
+                share: Self::format_ratio(shares, total_supply),
+            })
+            .collect::<Vec<ProviderItem>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, list)))
+    }
+
+    pub async fn portfolio(
+        data: web::Data<AppData>,
+        path: web::Path<String>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let account = path.into_inner().to_lowercase();
+        let store = &data.store;
+        let mut positions = HashMap::<u64, ([U256; 2], U256, U256, U256)>::new();
+        let balances = store
+            .read_account_balances(account.clone())
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        for (proposal_id, index, balance) in balances {
+            //流水不完整时余额可能为负,按0处理
+            let balance = U256::from_dec_str(&balance).unwrap_or_default();
+            positions.entry(proposal_id).or_default().0[index as usize % 2] = balance;
+        }
+        let events = store
+            .read_account_liquidity_events(account.clone())
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        for event in events {
+            positions.entry(event.proposal_id).or_default().1 =
+                U256::from_dec_str(&event.shares).unwrap_or_default();
+        }
+        let totals = store
+            .read_account_trade_totals(account)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        for (proposal_id, side, total) in totals {
+            let total = U256::from_dec_str(&total).unwrap_or_default();
+            let entry = positions.entry(proposal_id).or_default();
+            match side.as_str() {
+                "Buy" => entry.2 = total,
+                _ => entry.3 = total,
+            }
+        }
+
+        let mut list = Vec::with_capacity(positions.len());
+        for (proposal_id, (balances, lp_shares, bought, sold)) in positions {
+            let price = store
+                .read_latest_price(proposal_id)
+                .await
+                .map_err(|_| XProtocolError::InternalServerError)?
+                .map(|(b0, b1)| Self::price_ratio(&b0, &b1))
+                .unwrap_or_default();
+            //按最新价格估算持仓价值
+            let value = balances[0]
+                .saturating_mul(price[0])
+                .saturating_add(balances[1].saturating_mul(price[1]))
+                / U256::exp10(PRICE_DECIMALS);
+            let decimals = data.get_proposal_decimals(proposal_id);
+            list.push(PortfolioItem {
+                proposal_id,
+                decimals,
+                balances: balances.map(|b| Amount::new(b.to_string(), decimals)),
+                lp_shares: Amount::new(lp_shares.to_string(), decimals),
+                bought: Amount::new(bought.to_string(), decimals),
+                sold: Amount::new(sold.to_string(), decimals),
+                price: price.map(|p| Self::format_decimal(p, PRICE_DECIMALS)),
+                value: Amount::new(value.to_string(), decimals),
+            });
+        }
+        list.sort_by_key(|item| Reverse(item.proposal_id));
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, list)))
+    }
+
+    pub async fn proposal_pnl(
+        data: web::Data<AppData>,
+        path: web::Path<u64>,
+        info: web::Query<Pagination>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let proposal_id = path.into_inner();
+        let Pagination { page, count } = Self::check_pagination(info)?;
+        let store = &data.store;
+        let rows = store
+            .read_pnl(Some(proposal_id), None)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let (price, result) = Self::pnl_market(store, proposal_id).await?;
+        let decimals = data.get_proposal_decimals(proposal_id);
+        let mut accounts = HashMap::<String, Vec<pnl::Model>>::new();
+        for row in rows {
+            accounts.entry(row.account.clone()).or_default().push(row);
+        }
+        let mut list = accounts
+            .into_iter()
+            .map(|(account, rows)| {
+                Pnl::valuation(proposal_id, account, decimals, &rows, price, result)
+            })
+            .collect::<Vec<PnlItem>>();
+        list.sort_by_key(|item| Reverse(item.total.raw.parse::<i128>().unwrap_or_default()));
+        let total = list.len();
+        let list = list
+            .into_iter()
+            .skip((page - 1) * count)
+            .take(count)
+            .collect::<Vec<PnlItem>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(
+            StatusCode::OK,
+            ProposalList {
+                current: page,
+                total,
+                list,
+            },
+        )))
+    }
+
+    pub async fn account_pnl(
+        data: web::Data<AppData>,
+        path: web::Path<String>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let account = path.into_inner().to_lowercase();
+        let store = &data.store;
+        let rows = store
+            .read_pnl(None, Some(account.clone()))
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let mut proposals = HashMap::<u64, Vec<pnl::Model>>::new();
+        for row in rows {
+            proposals.entry(row.proposal_id).or_default().push(row);
+        }
+        let mut list = Vec::with_capacity(proposals.len());
+        for (proposal_id, rows) in proposals {
+            let (price, result) = Self::pnl_market(store, proposal_id).await?;
+            list.push(Pnl::valuation(
+                proposal_id,
+                account.clone(),
+                data.get_proposal_decimals(proposal_id),
+                &rows,
+                price,
+                result,
+            ));
+        }
+        list.sort_by_key(|item| Reverse(item.proposal_id));
+        //汇总所有提案,各提案先换算到统一精度
+        let sum = |f: fn(&PnlItem) -> Option<&Amount>| {
+            let total = list
+                .iter()
+                .filter_map(|item| f(item).map(|v| (v, item.decimals)))
+                .map(|(v, decimals)| {
+                    let factor = 10i128.pow(NORMALIZED_DECIMALS.saturating_sub(decimals) as u32);
+                    v.raw.parse::<i128>().unwrap_or_default().saturating_mul(factor)
+                })
+                .fold(0i128, |acc, v| acc.saturating_add(v));
+            Amount::new(total.to_string(), NORMALIZED_DECIMALS)
+        };
+        let res = AccountPnl {
+            realized: sum(|item| Some(&item.realized)),
+            unrealized: sum(|item| Some(&item.unrealized)),
+            payout: sum(|item| item.payout.as_ref()),
+            total: sum(|item| Some(&item.total)),
+            list,
+        };
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, res)))
+    }
+
+    pub async fn leaderboard(
+        data: web::Data<AppData>,
+        path: web::Path<LeaderboardKind>,
+        query: web::Query<LeaderboardQuery>,
+        info: web::Query<Pagination>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        let kind = path.into_inner();
+        let LeaderboardQuery { window, sort } = query.into_inner();
+        let window = window.unwrap_or(LeaderboardWindow::All);
+        let Pagination { page, count } = Self::check_pagination(info)?;
+        let sorts = kind.sorts();
+        let sort = match sort {
+            Some(sort) => *sorts
+                .iter()
+                .find(|&&s| s == sort)
+                .ok_or(XProtocolError::BadRequest)?,
+            None => sorts[0],
+        };
+        let (total, rows) = data
+            .store
+            .read_leaderboard(
+                format!("{:?}", kind),
+                format!("{:?}", window),
+                sort,
+                count,
+                page,
+            )
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        let list = rows
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| LeaderboardItem {
+                rank: ((page - 1) * count + i + 1) as u64,
+                account: row.account,
+                volume: Amount::new(row.volume, NORMALIZED_DECIMALS),
+                pnl: Amount::new(row.pnl, NORMALIZED_DECIMALS),
+                proposals: row.proposals,
+                liquidity: Amount::new(row.liquidity, NORMALIZED_DECIMALS),
+                estimated_fees: Amount::new(row.estimated_fees, NORMALIZED_DECIMALS),
+                updated_at: row.updated_at,
+            })
+            .collect::<Vec<LeaderboardItem>>();
+        Ok(HttpResponse::Ok().json(XResponse::new(
+            StatusCode::OK,
+            ProposalList {
+                current: page,
+                total,
+                list,
+            },
+        )))
+    }
+
+    //最新价格和提案结果
+    async fn pnl_market(
+        store: &StoreDB,
+        proposal_id: u64,
+    ) -> Result<([U256; 2], Option<u64>), XProtocolError> {
+        let price = store
+            .read_latest_price(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .map(|(b0, b1)| Self::price_ratio(&b0, &b1))
+            .unwrap_or_default();
+        let result = store
+            .read_resolution(proposal_id)
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?
+            .map(|resolution| resolution.result);
+        Ok((price, result))
+    }
+
+    /*
+        校验管理接口的X-Admin-Token
+        未配置admin_token时管理接口不可用
+    */
+    fn check_admin(req: &HttpRequest, data: &AppData) -> Result<(), XProtocolError> {
+        let admin_token = data
+            .admin_token
+            .as_deref()
+            .ok_or(XProtocolError::MethodNotAllowed)?;
+        let token = req
+            .headers()
+            .get("X-Admin-Token")
+            .and_then(|v| v.to_str().ok())
+            .ok_or(XProtocolError::Unauthorized)?;
+        if !Self::constant_time_eq(token.as_bytes(), admin_token.as_bytes()) {
+            return Err(XProtocolError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    //比较全部字节,耗时与第一个不同字节的位置无关
+    fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+
+    /*
+        管理员提交币种的美元价格,请求头X-Admin-Token需与配置一致
+        未配置链上定价合约时生效,下一次刷新美元价格时重算提案的美元数据
+    */
+    pub async fn post_price(
+        req: HttpRequest,
+        data: web::Data<AppData>,
+        body: web::Json<TokenPriceReq>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        Self::check_admin(&req, &data)?;
+        let TokenPriceReq { token, price } = body.into_inner();
+        let token = token.to_lowercase();
+        if !data.get_coins().contains(&token) {
+            return Err(XProtocolError::NotFound);
+        }
+        let price =
+            Self::parse_decimal(&price, USD_DECIMALS as usize).ok_or(XProtocolError::BadRequest)?;
+        data.store
+            .write_token_price(token, price, Self::now())
+            .await
+            .map_err(|_| XProtocolError::InternalServerError)?;
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, ())))
+    }
+
+    //各rpc节点的最新区块,延迟和错误率
+    pub async fn rpc_status(
+        req: HttpRequest,
+        data: web::Data<AppData>,
+    ) -> Result<HttpResponse, XProtocolError> {
+        Self::check_admin(&req, &data)?;
+        Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, data.rpc.status())))
+    }
+
     pub async fn backstage(
         req: HttpRequest,
         data: web::Data<AppData>,
@@ -282,12 +1220,12 @@ impl Handlers {
             ProposalState::Formal => {
                 Self::category_filter(&query, &mut combine);
                 Self::insert_combine(&mut combine, ">", "close_time".into(), now.to_string());
-                Self::liquidity_filter(&query, &mut order_map);
+                Self::liquidity_filter(&query, &mut combine, &mut order_map, now);
                 Self::about_me(store, &query).await?
             }
             ProposalState::End /* | ProposalState::Referendum */ => {
                 Self::category_filter(&query, &mut combine);
-                Self::liquidity_filter(&query, &mut order_map);
+                Self::liquidity_filter(&query, &mut combine, &mut order_map, now);
                 Self::about_me(store, &query).await?
             }
             _ => return Err(XProtocolError::MethodNotAllowed),
@@ -334,6 +1272,7 @@ impl Handlers {
             .map(|v| Coins {
                 address: v.0.clone(),
                 symbol: v.1.clone(),
+                decimals: data.get_decimals(&v.0),
             })
             .collect::<Vec<Coins>>();
         Ok(HttpResponse::Ok().json(XResponse::new(StatusCode::OK, list)))
@@ -382,6 +1321,68 @@ impl Handlers {
         Ok((query, origin_page, count))
     }
 
+    //part/total,保留RATIO_DECIMALS位小数
+    fn format_ratio(part: U256, total: U256) -> String {
+        if total.is_zero() {
+            return "0".to_string();
+        }
+        let ratio = part.saturating_mul(U256::exp10(RATIO_DECIMALS)) / total;
+        Self::format_decimal(ratio, RATIO_DECIMALS)
+    }
+
+    //value按decimals位小数放大,输出十进制字符串
+    fn format_decimal(value: U256, decimals: usize) -> String {
+        let scale = U256::exp10(decimals);
+        format!(
+            "{}.{:0>width$}",
+            value / scale,
+            (value % scale).to_string(),
+            width = decimals
+        )
+    }
+
+    //十进制小数按decimals位放大,小数位超出或格式错误返回None
+    fn parse_decimal(value: &str, decimals: usize) -> Option<U256> {
+        let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || fraction.len() > decimals
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let integer = U256::from_dec_str(if integer.is_empty() { "0" } else { integer }).ok()?;
+        let fraction = format!("{:0<width$}", fraction, width = decimals);
+        let fraction = U256::from_dec_str(&fraction).unwrap_or_default();
+        integer
+            .checked_mul(U256::exp10(decimals))?
+            .checked_add(fraction)
+    }
+
+    /*
+        由两个选项池的余额计算价格,按PRICE_DECIMALS放大
+        与原百分比一致,选项0的价格为balance0占比
+    */
+    fn price_ratio(balance0: &str, balance1: &str) -> [U256; 2] {
+        let scale = U256::exp10(PRICE_DECIMALS);
+        let balance0 = U256::from_dec_str(balance0).unwrap_or_default();
+        let balance1 = U256::from_dec_str(balance1).unwrap_or_default();
+        let total = balance0.saturating_add(balance1);
+        if total.is_zero() {
+            return [scale / 2, scale / 2];
+        }
+        let price0 = balance0.saturating_mul(scale) / total;
+        [price0, scale - price0]
+    }
+
+    fn check_pagination(info: web::Query<Pagination>) -> Result<Pagination, XProtocolError> {
+        let pagination = info.into_inner();
+        if pagination.page == 0 || pagination.count == 0 {
+            Err(XProtocolError::BadRequest)
+        } else {
+            Ok(pagination)
+        }
+    }
+
     fn get_option<T>(map: &HashMap<String, String>, key: &'static str) -> Option<T>
     where
         T: FromStr,
@@ -400,7 +1401,7 @@ impl Handlers {
     fn insert_combine(combine: &mut CombineMap, op: &'static str, key: String, value: String) {
         combine
             .entry(op) //空的或者被占用的
-            .or_insert_with(HashMap::new) //空的就输入默认值,并返回当前值
+            .or_default() //空的就输入默认值,并返回当前值
             .insert(key, value); //返回值修改
     }
 
@@ -429,20 +1430,28 @@ impl Handlers {
             .map_err(|_| XProtocolError::InsufficientStorage)
     }
 
-    fn liquidity_filter(query: &HashMap<String, String>, order: &mut Vec<(&str, bool)>) {
+    /*
+        按liquidity参数的序号排序,同值按提案ID
+        即将结束只包含尚未结束的提案
+    */
+    fn liquidity_filter(
+        query: &HashMap<String, String>,
+        combine: &mut CombineMap,
+        order: &mut Vec<(&str, bool)>,
+        now: u64,
+    ) {
         let key = "liquidity";
-        let keys = ["volume24", "totalvolume", "highliquidity"];
-        if let Ok(liquidity) = Self::get_value::<usize>(query, key) {
-            if let Some(&key) = keys.get(liquidity) {
-                Self::insert_order_map(order, key, false);
-            } //0 1 2 3
-            if liquidity == 5 {
-                Self::insert_order_map(order, "close_time", true);
-            }
-            if liquidity == 4 {
-                Self::insert_order_map(order, "proposal_id", false);
-            } else {
-                Self::insert_order_map(order, "proposal_id", true); //空
+        if let Ok(index) = Self::get_value::<usize>(query, key) {
+            if let Some(sort) = ProposalSort::SORTS.get(index) {
+                for &(key, asc) in sort.order() {
+                    Self::insert_order_map(order, key, asc);
+                }
+                if *sort == ProposalSort::ClosingSoon {
+                    Self::insert_combine(combine, ">", "close_time".into(), now.to_string());
+                }
+                if *sort != ProposalSort::Newest {
+                    Self::insert_order_map(order, "proposal_id", true);
+                }
             }
         }
     }
@@ -475,3 +1484,106 @@ impl Handlers {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use web3::types::U256;
+
+    use super::{Amount, Handlers, MAX_CANDLES, MAX_DECIMALS, PRICE_DECIMALS};
+
+    fn price(p0: u64) -> [U256; 2] {
+        [U256::from(p0), U256::from(100 - p0)]
+    }
+
+    #[test]
+    fn amount_trims_fraction() {
+        let value = |raw: &str, decimals| Amount::new(raw.to_string(), decimals).value;
+        assert_eq!(value("1500000", 6), "1.5");
+        assert_eq!(value("-25", 2), "-0.25");
+        assert_eq!(value("-0", 2), "0");
+        assert_eq!(value("100", 0), "100");
+        assert_eq!(value("abc", 18), "0");
+        assert_eq!(value(&U256::MAX.to_string(), MAX_DECIMALS).len(), 79);
+    }
+
+    #[test]
+    fn parse_decimal_scales_and_rejects() {
+        assert_eq!(
+            Handlers::parse_decimal("1.5", 6),
+            Some(U256::from(1_500_000))
+        );
+        assert_eq!(Handlers::parse_decimal(".5", 2), Some(U256::from(50)));
+        assert_eq!(Handlers::parse_decimal("7", 0), Some(U256::from(7)));
+        assert_eq!(Handlers::parse_decimal("1.234", 2), None);
+        assert_eq!(Handlers::parse_decimal("-1", 2), None);
+        assert_eq!(Handlers::parse_decimal(".", 2), None);
+        assert_eq!(Handlers::parse_decimal("1e3", 2), None);
+        assert_eq!(Handlers::parse_decimal(&U256::MAX.to_string(), 1), None);
+    }
+
+    #[test]
+    fn price_ratio_by_balance_share() {
+        let scale = U256::exp10(PRICE_DECIMALS);
+        assert_eq!(Handlers::price_ratio("0", "0"), [scale / 2, scale / 2]);
+        assert_eq!(
+            Handlers::price_ratio("1", "3"),
+            [scale / 4, scale - scale / 4]
+        );
+        //两个价格之和始终为1
+        let [p0, p1] = Handlers::price_ratio("1", "2");
+        assert_eq!(p0 + p1, scale);
+        assert_eq!(Handlers::price_ratio("x", "5"), [U256::zero(), scale]);
+    }
+
+    #[test]
+    fn admin_token_comparison() {
+        assert!(Handlers::constant_time_eq(b"secret", b"secret"));
+        assert!(!Handlers::constant_time_eq(b"secret", b"secreT"));
+        assert!(!Handlers::constant_time_eq(b"secret", b"secret1"));
+        assert!(!Handlers::constant_time_eq(b"", b"secret"));
+    }
+
+    #[test]
+    fn candle_range_aligns_to_buckets() {
+        //to所在的桶包含在内
+        assert_eq!(
+            Handlers::candle_range(Some(310), 950, 300),
+            Some((300, 1200))
+        );
+        assert_eq!(
+            Handlers::candle_range(None, 300 * MAX_CANDLES, 300),
+            Some((300, 300 * (MAX_CANDLES + 1)))
+        );
+        assert_eq!(Handlers::candle_range(Some(1300), 950, 300), None);
+        assert_eq!(
+            Handlers::candle_range(Some(0), 300 * MAX_CANDLES, 300),
+            None
+        );
+        //对齐后溢出
+        assert_eq!(
+            Handlers::candle_range(Some(u64::MAX - 10), u64::MAX, 300),
+            None
+        );
+    }
+
+    #[test]
+    fn bucket_candles_carries_close() {
+        let prices = vec![
+            (310, price(60)),
+            (320, price(40)),
+            (350, price(55)),
+            (950, price(70)),
+        ];
+        let list = Handlers::bucket_candles(0, 1200, 300, price(50), prices.into_iter());
+        assert_eq!(
+            list.iter().map(|(ts, _)| *ts).collect::<Vec<_>>(),
+            vec![0, 300, 600, 900]
+        );
+        //没有价格的桶沿用上一个收盘价
+        assert_eq!(list[0].1[0], [U256::from(50); 4]);
+        assert_eq!(list[1].1[0], [50, 60, 40, 55].map(U256::from));
+        assert_eq!(list[1].1[1], [50, 60, 40, 45].map(U256::from));
+        assert_eq!(list[2].1[0], [U256::from(55); 4]);
+        assert_eq!(list[3].1[0], [55, 70, 55, 70].map(U256::from));
+    }
+}
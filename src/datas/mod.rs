@@ -3,8 +3,8 @@ pub(crate) mod data;
 pub(crate) mod error;
 pub(crate) mod handle;
 
-use self::data::AppData;
-use crate::db::StoreDB;
+use self::{data::AppData, handle::ProposalSort};
+use crate::{db::StoreDB, xprotocol::transport::Failover};
 
 use actix_web::web;
 use chrono::Local;
@@ -36,7 +36,12 @@ pub fn init_logger() {
         .init();
 }
 
-pub async fn init_app_data(url: &str, chain_id: u32) -> BoxedResult<web::Data<AppData>> {
+pub async fn init_app_data(
+    url: &str,
+    chain_id: u32,
+    admin_token: Option<String>,
+    rpc: Failover,
+) -> BoxedResult<web::Data<AppData>> {
     let pool = Database::connect(url).await?;
     let store_db = StoreDB::init_db(pool, chain_id).await?;
 
@@ -51,20 +56,22 @@ pub async fn init_app_data(url: &str, chain_id: u32) -> BoxedResult<web::Data<Ap
         "Other",
     ];
 
-    let liquidity = vec![
-        "24h volume",
-        "48h volume",
-        "Total volume",
-        "Newest",
-        "Closing soon",
-    ];
+    let liquidity = ProposalSort::SORTS.to_vec();
     let proposals = store_db.read_proposals().await?;
-    let app_data = AppData::new(store_db.clone(), categories, liquidity, chain_id, proposals);
+    let app_data = AppData::new(
+        store_db.clone(),
+        categories,
+        liquidity,
+        chain_id,
+        proposals,
+        admin_token,
+        rpc,
+    );
     let data = web::Data::new(app_data);
 
     let list = store_db.read_coins_support().await?;
-    for (addr, symbol, flag) in list.iter() {
-        data.insert_support(addr.to_string(), symbol.to_string(), *flag)?;
+    for (addr, symbol, flag, decimals) in list.iter() {
+        data.insert_support(addr.to_string(), symbol.to_string(), *decimals, *flag)?;
     }
     Ok(data)
 }
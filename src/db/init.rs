@@ -2,6 +2,74 @@ use super::StoreDB;
 use crate::datas::BoxedResult;
 use sea_orm::{ConnectionTrait, DatabaseBackend, DbConn, Statement, TransactionTrait};
 impl StoreDB {
+    /*
+        旧库升级: 字段不存在时追加
+    */
+    async fn add_column<C>(conn: &C, table: &str, column: &str, definition: &str) -> BoxedResult<()>
+    where
+        C: ConnectionTrait,
+    {
+        let exist = conn
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT COUNT(*) AS `count` FROM information_schema.COLUMNS
+                    WHERE `TABLE_SCHEMA` = DATABASE() AND `TABLE_NAME` = ? AND `COLUMN_NAME` = ?;
+                "#,
+                vec![table.into(), column.into()],
+            ))
+            .await?;
+        let count: i64 = match exist {
+            Some(row) => row.try_get("", "count")?,
+            None => 0,
+        };
+        if count == 0 {
+            conn.execute(Statement::from_string(
+                DatabaseBackend::MySql,
+                format!("ALTER TABLE `{}` ADD COLUMN `{}` {};", table, column, definition),
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    /*
+        旧库升级: 字段类型不一致时修改
+    */
+    async fn modify_column<C>(
+        conn: &C,
+        table: &str,
+        column: &str,
+        data_type: &str,
+        definition: &str,
+    ) -> BoxedResult<()>
+    where
+        C: ConnectionTrait,
+    {
+        let current = conn
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT CAST(`DATA_TYPE` AS CHAR) AS `data_type` FROM information_schema.COLUMNS
+                    WHERE `TABLE_SCHEMA` = DATABASE() AND `TABLE_NAME` = ? AND `COLUMN_NAME` = ?;
+                "#,
+                vec![table.into(), column.into()],
+            ))
+            .await?;
+        let current: String = match current {
+            Some(row) => row.try_get("", "data_type")?,
+            None => return Ok(()),
+        };
+        if !current.eq_ignore_ascii_case(data_type) {
+            conn.execute(Statement::from_string(
+                DatabaseBackend::MySql,
+                format!("ALTER TABLE `{}` MODIFY COLUMN `{}` {};", table, column, definition),
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
     pub async fn init_db(pool: DbConn, chain_id: u32) -> BoxedResult<Self> {
         let txn = pool.begin().await?;
 
@@ -41,6 +109,7 @@ impl StoreDB {
                 `address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
                 `symbol` varchar(10) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
                 `flag` tinyint(1) NOT NULL DEFAULT '0',
+                `decimals` tinyint unsigned NOT NULL DEFAULT 18,
                 PRIMARY KEY (`address`),
                 UNIQUE KEY `address` (`address`)
               ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -49,6 +118,20 @@ impl StoreDB {
         ))
         .await?;
 
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `token_prices` (
+                `token` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `price` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `updated_at` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`token`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#
+            .to_owned(),
+        ))
+        .await?;
+
         txn.execute(Statement::from_string(
             DatabaseBackend::MySql,
             r#"
@@ -57,6 +140,9 @@ impl StoreDB {
                 `ts` int NOT NULL DEFAULT 0,
                 `token1` bigint NOT NULL DEFAULT '0',
                 `token2` bigint NOT NULL DEFAULT '0',
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `balance0` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `balance1` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
                 PRIMARY KEY (`proposal_id`, `ts`),
                 UNIQUE KEY `proposal_id` (`proposal_id`, `ts`)
               ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -72,14 +158,33 @@ impl StoreDB {
                 `proposal_id` int unsigned NOT NULL AUTO_INCREMENT,
                 `address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
                 `token` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
-                `liquidity` bigint NOT NULL DEFAULT 0,
+                `liquidity` decimal(65,0) NOT NULL DEFAULT 0,
                 `create_time` int NOT NULL DEFAULT 0,
                 `close_time` int NOT NULL DEFAULT 0,
                 `audit_state` enum('NotReviewed','Passed','NotPassed') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'NotReviewed',
                 `category` int NOT NULL DEFAULT 0,
                 `state` enum('Original','Formal','End') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'Original',
-                `volume` bigint NOT NULL DEFAULT 0,
-                `volume24` bigint NOT NULL DEFAULT 0,
+                `volume` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume24` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume48` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume7d` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume_usd` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume24_usd` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume48_usd` decimal(65,0) NOT NULL DEFAULT 0,
+                `volume7d_usd` decimal(65,0) NOT NULL DEFAULT 0,
+                `tvl_usd` decimal(65,0) NOT NULL DEFAULT 0,
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `token1_address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `token2_address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `title` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `details` varchar(2048) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `outcome0` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `outcome1` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `fee_ratio` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `creater` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `initial_liquidity` decimal(65,0) NOT NULL DEFAULT 0,
+                `state_block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `trades_backfilled` tinyint(1) NOT NULL DEFAULT 0,
                 PRIMARY KEY (`proposal_id`),
                 UNIQUE KEY `address` (`address`)
               ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -94,6 +199,7 @@ impl StoreDB {
                 `proposal_id` int NOT NULL DEFAULT '0',
                 `address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
                 `relations` enum('Liquidity','Create','Trade') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
                 PRIMARY KEY (`proposal_id`,`address`,`relations`) USING BTREE,
                 UNIQUE KEY `proposal_id` (`proposal_id`,`address`,`relations`)
               ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -101,6 +207,254 @@ impl StoreDB {
         ))
         .await?;
 
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `block_hash` (
+                `number` BIGINT(20) unsigned NOT NULL,
+                `hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                PRIMARY KEY (`number`),
+                UNIQUE KEY `number` (`number`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `block_times` (
+                `number` BIGINT(20) unsigned NOT NULL,
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`number`),
+                KEY `ts` (`ts`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `trades` (
+                `transaction_hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `log_index` int unsigned NOT NULL DEFAULT 0,
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `token` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `side` enum('Buy','Sell') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'Buy',
+                `amount` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`transaction_hash`, `log_index`),
+                KEY `proposal_id` (`proposal_id`, `block`),
+                KEY `account` (`account`, `block`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `liquidity_events` (
+                `transaction_hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `log_index` int unsigned NOT NULL DEFAULT 0,
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `side` enum('Add','Remove') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'Add',
+                `amount` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `shares` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`transaction_hash`, `log_index`),
+                KEY `proposal_id` (`proposal_id`, `block`),
+                KEY `account` (`account`, `block`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `resolutions` (
+                `proposal_id` int unsigned NOT NULL,
+                `address` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `result` int unsigned NOT NULL DEFAULT 0,
+                `end_balance_token0` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `end_balance_token1` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `end_total_supply` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`proposal_id`),
+                UNIQUE KEY `proposal_id` (`proposal_id`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `transfers` (
+                `transaction_hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `log_index` int unsigned NOT NULL DEFAULT 0,
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `token` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `token_index` int unsigned NOT NULL DEFAULT 0,
+                `sender` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `receiver` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `amount` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`transaction_hash`, `log_index`),
+                KEY `proposal_id` (`proposal_id`, `block`),
+                KEY `sender` (`sender`),
+                KEY `receiver` (`receiver`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `pnl` (
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `token_index` int unsigned NOT NULL DEFAULT 0,
+                `quantity` varchar(80) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `cost` varchar(80) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `realized` varchar(80) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `bought` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `sold` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `block` BIGINT(20) unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`proposal_id`, `account`, `token_index`),
+                KEY `account` (`account`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `pnl_realized` (
+                `transaction_hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `log_index` int unsigned NOT NULL DEFAULT 0,
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `token_index` int unsigned NOT NULL DEFAULT 0,
+                `realized` varchar(80) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`transaction_hash`, `log_index`),
+                KEY `proposal_id` (`proposal_id`),
+                KEY `ts` (`ts`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `lp_fees` (
+                `transaction_hash` varchar(66) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `log_index` int unsigned NOT NULL DEFAULT 0,
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `proposal_id` int unsigned NOT NULL DEFAULT 0,
+                `fee` varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0',
+                `ts` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`transaction_hash`, `log_index`, `account`),
+                KEY `proposal_id` (`proposal_id`),
+                KEY `ts` (`ts`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"
+            CREATE TABLE IF NOT EXISTS `leaderboard` (
+                `kind` enum('Traders','Creators','Lps') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'Traders',
+                `period` enum('Day','Week','Month','All') CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT 'All',
+                `account` varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '',
+                `volume` decimal(65,0) NOT NULL DEFAULT 0,
+                `pnl` decimal(65,0) NOT NULL DEFAULT 0,
+                `proposals` int unsigned NOT NULL DEFAULT 0,
+                `liquidity` decimal(65,0) NOT NULL DEFAULT 0,
+                `estimated_fees` decimal(65,0) NOT NULL DEFAULT 0,
+                `updated_at` int unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`kind`, `period`, `account`)
+              ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+		"#.to_owned(),
+        ))
+        .await?;
+
+        for table in ["price", "proposals", "relations"] {
+            Self::add_column(&txn, table, "block", "BIGINT(20) unsigned NOT NULL DEFAULT 0").await?;
+        }
+        let columns = [
+            ("token1_address", "varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("token2_address", "varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("title", "varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("details", "varchar(2048) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("outcome0", "varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("outcome1", "varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("fee_ratio", "varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0'"),
+            ("creater", "varchar(42) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT ''"),
+            ("initial_liquidity", "decimal(65,0) NOT NULL DEFAULT 0"),
+            ("state_block", "BIGINT(20) unsigned NOT NULL DEFAULT 0"),
+            ("trades_backfilled", "tinyint(1) NOT NULL DEFAULT 0"),
+        ];
+        for (column, definition) in columns {
+            Self::add_column(&txn, "proposals", column, definition).await?;
+        }
+        //bigint放不下18位小数的原始数量
+        for column in ["liquidity", "volume", "volume24"] {
+            Self::modify_column(
+                &txn,
+                "proposals",
+                column,
+                "decimal",
+                "decimal(65,0) NOT NULL DEFAULT 0",
+            )
+            .await?;
+        }
+        for column in [
+            "volume48",
+            "volume7d",
+            "volume_usd",
+            "volume24_usd",
+            "volume48_usd",
+            "volume7d_usd",
+            "tvl_usd",
+        ] {
+            Self::add_column(&txn, "proposals", column, "decimal(65,0) NOT NULL DEFAULT 0").await?;
+        }
+        Self::add_column(&txn, "coins", "decimals", "tinyint unsigned NOT NULL DEFAULT 18").await?;
+        Self::add_column(&txn, "leaderboard", "estimated_fees", "decimal(65,0) NOT NULL DEFAULT 0")
+            .await?;
+
+        //价格改为存两个选项池的原始余额,旧数据的百分比按比例写入余额
+        for column in ["balance0", "balance1"] {
+            Self::add_column(
+                &txn,
+                "price",
+                column,
+                "varchar(78) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL DEFAULT '0'",
+            )
+            .await?;
+        }
+        txn.execute(Statement::from_string(
+            DatabaseBackend::MySql,
+            r#"UPDATE `price` SET `balance0` = CAST(`token1` AS CHAR), `balance1` = CAST(`token2` AS CHAR)
+                WHERE `balance0` = '0' AND `balance1` = '0';"#
+                .to_owned(),
+        ))
+        .await?;
+
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"INSERT IGNORE INTO `block` (`id`, `block`, `step`) VALUES
@@ -2,35 +2,55 @@ use std::str::FromStr;
 
 use sea_orm::{
     sea_query::{BinOper, Expr, IntoColumnRef, SimpleExpr},
-    ColumnTrait, Condition, DeriveColumn, EntityTrait, EnumIter, IdenStatic, Order, PaginatorTrait,
-    QueryFilter, QueryOrder, QuerySelect, 
+    ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DbErr, DeriveColumn, EntityTrait,
+    EnumIter, FromQueryResult, IdenStatic, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Statement,
 };
 
 use super::StoreDB;
 use crate::{
-    datas::{handle::CombineMap, BoxedResult},
+    datas::{
+        handle::{CombineMap, LeaderboardRow, ProposalDetail},
+        BoxedResult,
+    },
     db::FromSymbol,
     entity::{prelude::*, *},
 };
 impl StoreDB {
-    pub async fn read_coins_support(&self) -> BoxedResult<Vec<(String, String, bool)>> {
+    pub async fn read_coins_support(&self) -> BoxedResult<Vec<(String, String, bool, u8)>> {
         #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
         enum QueryAs {
             Address,
             Symbol,
             Flag,
+            Decimals,
         }
         Coins::find()
             .select_only()
             .column_as(coins::Column::Address, QueryAs::Address)
             .column_as(coins::Column::Symbol, QueryAs::Symbol)
             .column_as(coins::Column::Flag, QueryAs::Flag)
+            .column_as(coins::Column::Decimals, QueryAs::Decimals)
             .into_values::<_, QueryAs>()
             .all(&self.pool)
             .await
             .map_err(|e| e.into())
     }
 
+    //管理员提交的币种美元价格
+    pub async fn read_token_price(&self, token: String) -> BoxedResult<Option<String>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Price,
+        }
+        TokenPrices::find_by_id(token)
+            .select_only()
+            .column_as(token_prices::Column::Price, QueryAs::Price)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn read_block(&self, chain_id: u32) -> BoxedResult<(u64, u64)> {
         #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
         enum QueryAs {
@@ -47,18 +67,162 @@ impl StoreDB {
             .ok_or_else(|| "id not found".into())
     }
 
-    pub async fn read_proposals(&self) -> BoxedResult<Vec<(u64, String, String)>> {
+    pub async fn read_block_hash(&self, number: u64) -> BoxedResult<Option<String>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Hash,
+        }
+        BlockHash::find_by_id(number)
+            .select_only()
+            .column_as(block_hash::Column::Hash, QueryAs::Hash)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_block_hashes(&self, to: u64) -> BoxedResult<Vec<(u64, String)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Number,
+            Hash,
+        }
+        BlockHash::find()
+            .select_only()
+            .column_as(block_hash::Column::Number, QueryAs::Number)
+            .column_as(block_hash::Column::Hash, QueryAs::Hash)
+            .filter(block_hash::Column::Number.lte(to))
+            .order_by(block_hash::Column::Number, Order::Desc)
+            .into_values::<_, QueryAs>()
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //已入库的提案ID及其创建区块,按提案ID升序
+    pub async fn read_proposal_blocks(&self) -> BoxedResult<Vec<(u64, u64)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            ProposalId,
+            Block,
+        }
+        Proposals::find()
+            .select_only()
+            .column_as(proposals::Column::ProposalId, QueryAs::ProposalId)
+            .column_as(proposals::Column::Block, QueryAs::Block)
+            .filter(proposals::Column::Address.ne(""))
+            .order_by(proposals::Column::ProposalId, Order::Asc)
+            .into_values::<_, QueryAs>()
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //交易流水还没有补齐的提案及其创建区块
+    pub async fn read_proposals_without_trades(&self) -> BoxedResult<Vec<(u64, String, u64)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            ProposalId,
+            Address,
+            Block,
+        }
+        Proposals::find()
+            .select_only()
+            .column_as(proposals::Column::ProposalId, QueryAs::ProposalId)
+            .column_as(proposals::Column::Address, QueryAs::Address)
+            .column_as(proposals::Column::Block, QueryAs::Block)
+            .filter(proposals::Column::Address.ne(""))
+            .filter(proposals::Column::TradesBackfilled.eq(false))
+            .order_by(proposals::Column::ProposalId, Order::Asc)
+            .into_values::<_, QueryAs>()
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //提案的创建区块
+    pub async fn read_proposal_block(&self, proposal_id: u64) -> BoxedResult<Option<u64>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Block,
+        }
+        Proposals::find()
+            .select_only()
+            .column_as(proposals::Column::Block, QueryAs::Block)
+            .filter(proposals::Column::ProposalId.eq(proposal_id))
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_block_time(&self, number: u64) -> BoxedResult<Option<u64>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Ts,
+        }
+        BlockTimes::find_by_id(number)
+            .select_only()
+            .column_as(Expr::cust("CAST(`ts` AS UNSIGNED)"), QueryAs::Ts)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /*
+        已记录的区块中,时间戳早于ts的最大区块和不早于ts的最小区块
+        用于缩小二分查找的区间,只考虑不超过hight的区块
+    */
+    pub async fn read_block_time_bounds(
+        &self,
+        ts: u64,
+        hight: u64,
+    ) -> BoxedResult<(Option<u64>, Option<u64>)> {
+        let row = self
+            .pool
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT
+                    (SELECT CAST(MAX(`number`) AS UNSIGNED) FROM `block_times`
+                        WHERE `ts` < ? AND `number` <= ?) AS `before`,
+                    (SELECT CAST(MIN(`number`) AS UNSIGNED) FROM `block_times`
+                        WHERE `ts` >= ? AND `number` <= ?) AS `after`;
+            "#,
+                vec![ts.into(), hight.into(), ts.into(), hight.into()],
+            ))
+            .await?;
+        match row {
+            Some(row) => Ok((
+                row.try_get::<Option<u64>>("", "before")?,
+                row.try_get::<Option<u64>>("", "after")?,
+            )),
+            None => Ok((None, None)),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub async fn read_proposals(
+        &self,
+    ) -> BoxedResult<Vec<(u64, String, String, String, String, String)>> {
         #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
         enum QueryAs {
             ProposalId,
             Address,
             State,
+            Token,
+            Token0,
+            Token1,
         }
         Proposals::find()
             .select_only()
             .column_as(proposals::Column::ProposalId, QueryAs::ProposalId)
             .column_as(proposals::Column::Address, QueryAs::Address)
             .column_as(proposals::Column::State, QueryAs::State)
+            .column_as(proposals::Column::Token, QueryAs::Token)
+            .column_as(proposals::Column::Token1Address, QueryAs::Token0)
+            .column_as(proposals::Column::Token2Address, QueryAs::Token1)
             .into_values::<_, QueryAs>()
             .all(&self.pool)
             .await
@@ -140,9 +304,24 @@ impl StoreDB {
             .filter(condition);
 
         let prepare = order_map.iter().fold(prepare, |prepare, (key, value)| {
-            if let Ok(key) = proposals::Column::from_str(key) {
+            if let Ok(column) = proposals::Column::from_str(key) {
                 let ord = [Order::Desc, Order::Asc][*value as usize];
-                prepare.order_by(key, ord)
+                match column {
+                    //数量按结算币种的精度换算后比较
+                    proposals::Column::Volume
+                    | proposals::Column::Volume24
+                    | proposals::Column::Volume48
+                    | proposals::Column::Volume7d
+                    | proposals::Column::Liquidity => prepare.order_by(
+                        Expr::cust(&format!(
+                            "`proposals`.`{}` / POW(10, IFNULL((SELECT `decimals` FROM `coins` \
+                                WHERE `coins`.`address` = `proposals`.`token`), 18))",
+                            key
+                        )),
+                        ord,
+                    ),
+                    _ => prepare.order_by(column, ord),
+                }
             } else {
                 prepare
             }
@@ -195,12 +374,12 @@ impl StoreDB {
     pub async fn read_history_proposal_id(
         &self,
         combine: &CombineMap,
-    ) -> BoxedResult<Vec<(u64, u64, u64)>> {
+    ) -> BoxedResult<Vec<(u64, String, String)>> {
         #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
         enum QueryAs {
             Ts,
-            Token1,
-            Token2,
+            Balance0,
+            Balance1,
         }
 
         let condition = combine
@@ -232,11 +411,408 @@ impl StoreDB {
             .filter(condition)
             .select_only()
             .column_as(price::Column::Ts, QueryAs::Ts)
-            .column_as(price::Column::Token1, QueryAs::Token1)
-            .column_as(price::Column::Token2, QueryAs::Token2)
+            .column_as(price::Column::Balance0, QueryAs::Balance0)
+            .column_as(price::Column::Balance1, QueryAs::Balance1)
             .into_values::<_, QueryAs>()
             .all(&self.pool)
             .await
             .map_err(|e| e.into())
     }
+
+    pub async fn read_trades(
+        &self,
+        count: usize,
+        page: usize,
+        proposal_id: Option<u64>,
+        account: Option<String>,
+    ) -> BoxedResult<(usize, Vec<trades::Model>)> {
+        let condition = Condition::all()
+            .add_option(proposal_id.map(|id| trades::Column::ProposalId.eq(id)))
+            .add_option(account.map(|account| trades::Column::Account.eq(account)));
+        let paginator = Trades::find()
+            .filter(condition)
+            .order_by(trades::Column::Block, Order::Desc)
+            .order_by(trades::Column::LogIndex, Order::Desc)
+            .paginate(&self.pool, count);
+        let total = paginator.num_pages().await?;
+
+        let r = paginator.fetch_page(page - 1).await?;
+        Ok((total, r))
+    }
+
+    pub async fn read_liquidity_events(
+        &self,
+        proposal_id: u64,
+    ) -> BoxedResult<Vec<liquidity_events::Model>> {
+        LiquidityEvents::find()
+            .filter(liquidity_events::Column::ProposalId.eq(proposal_id))
+            .order_by(liquidity_events::Column::Block, Order::Asc)
+            .order_by(liquidity_events::Column::LogIndex, Order::Asc)
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_proposal_liquidity(&self, proposal_id: u64) -> BoxedResult<String> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Liquidity,
+        }
+        Proposals::find_by_id(proposal_id)
+            .select_only()
+            .column_as(Expr::cust("CAST(`liquidity` AS CHAR)"), QueryAs::Liquidity)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await?
+            .ok_or_else(|| "id not found".into())
+    }
+
+    //提案的手续费率feeRatio
+    pub async fn read_proposal_fee_ratio(&self, proposal_id: u64) -> BoxedResult<String> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            FeeRatio,
+        }
+        Proposals::find_by_id(proposal_id)
+            .select_only()
+            .column_as(proposals::Column::FeeRatio, QueryAs::FeeRatio)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await?
+            .ok_or_else(|| "id not found".into())
+    }
+
+    pub async fn read_proposal_detail(
+        &self,
+        proposal_id: u64,
+    ) -> BoxedResult<Option<ProposalDetail>> {
+        Proposals::find_by_id(proposal_id)
+            .select_only()
+            .column(proposals::Column::ProposalId)
+            .column(proposals::Column::Address)
+            .column(proposals::Column::Token)
+            .column_as(proposals::Column::Token1Address, "token0")
+            .column_as(proposals::Column::Token2Address, "token1")
+            .column(proposals::Column::Title)
+            .column(proposals::Column::Details)
+            .column(proposals::Column::Outcome0)
+            .column(proposals::Column::Outcome1)
+            .column(proposals::Column::FeeRatio)
+            .column_as(Expr::cust("CAST(`category` AS UNSIGNED)"), "category")
+            .column_as(Expr::cust("CAST(`create_time` AS UNSIGNED)"), "create_time")
+            .column_as(Expr::cust("CAST(`close_time` AS UNSIGNED)"), "close_time")
+            .column(proposals::Column::AuditState)
+            .column(proposals::Column::State)
+            .column(proposals::Column::Creater)
+            .column_as(
+                Expr::cust("CAST(`initial_liquidity` AS CHAR)"),
+                "initial_liquidity",
+            )
+            .column_as(Expr::cust("CAST(`liquidity` AS CHAR)"), "liquidity")
+            .column_as(Expr::cust("CAST(`volume` AS CHAR)"), "volume")
+            .column_as(Expr::cust("CAST(`volume24` AS CHAR)"), "volume24")
+            .column_as(Expr::cust("CAST(`volume48` AS CHAR)"), "volume48")
+            .column_as(Expr::cust("CAST(`volume7d` AS CHAR)"), "volume7d")
+            .column_as(Expr::cust("CAST(`volume_usd` AS CHAR)"), "volume_usd")
+            .column_as(Expr::cust("CAST(`volume24_usd` AS CHAR)"), "volume24_usd")
+            .column_as(Expr::cust("CAST(`volume48_usd` AS CHAR)"), "volume48_usd")
+            .column_as(Expr::cust("CAST(`volume7d_usd` AS CHAR)"), "volume7d_usd")
+            .column_as(Expr::cust("CAST(`tvl_usd` AS CHAR)"), "tvl_usd")
+            .into_model::<ProposalDetail>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_resolution(
+        &self,
+        proposal_id: u64,
+    ) -> BoxedResult<Option<resolutions::Model>> {
+        Resolutions::find_by_id(proposal_id)
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /*
+        按Transfer流水汇总账户持有的选项代币
+        返回(提案ID, 选项序号, 余额)
+    */
+    pub async fn read_account_balances(
+        &self,
+        account: String,
+    ) -> BoxedResult<Vec<(u64, u64, String)>> {
+        self.pool
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT `proposal_id`, `token_index`, CAST(SUM(`delta`) AS CHAR) AS `balance` FROM (
+                    SELECT `proposal_id`, `token_index`, CAST(`amount` AS DECIMAL(65, 0)) AS `delta`
+                        FROM `transfers` WHERE `receiver` = ?
+                    UNION ALL
+                    SELECT `proposal_id`, `token_index`, -CAST(`amount` AS DECIMAL(65, 0)) AS `delta`
+                        FROM `transfers` WHERE `sender` = ?
+                ) AS `t` GROUP BY `proposal_id`, `token_index`;
+            "#,
+                vec![account.clone().into(), account.into()],
+            ))
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get::<u64>("", "proposal_id")?,
+                    row.try_get::<u64>("", "token_index")?,
+                    row.try_get::<String>("", "balance")?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DbErr>>()
+            .map_err(|e| e.into())
+    }
+
+    /*
+        账户在每个提案的买卖总额
+        返回(提案ID, Buy|Sell, 总额)
+    */
+    pub async fn read_account_trade_totals(
+        &self,
+        account: String,
+    ) -> BoxedResult<Vec<(u64, String, String)>> {
+        self.pool
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT `proposal_id`, CAST(`side` AS CHAR) AS `side`,
+                    CAST(SUM(CAST(`amount` AS DECIMAL(65, 0))) AS CHAR) AS `total`
+                    FROM `trades` WHERE `account` = ? GROUP BY `proposal_id`, `side`;
+            "#,
+                vec![account.into()],
+            ))
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get::<u64>("", "proposal_id")?,
+                    row.try_get::<String>("", "side")?,
+                    row.try_get::<String>("", "total")?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DbErr>>()
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_account_liquidity_events(
+        &self,
+        account: String,
+    ) -> BoxedResult<Vec<liquidity_events::Model>> {
+        LiquidityEvents::find()
+            .filter(liquidity_events::Column::Account.eq(account))
+            .order_by(liquidity_events::Column::Block, Order::Asc)
+            .order_by(liquidity_events::Column::LogIndex, Order::Asc)
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //最新一条价格 (balance0, balance1)
+    pub async fn read_latest_price(
+        &self,
+        proposal_id: u64,
+    ) -> BoxedResult<Option<(String, String)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Balance0,
+            Balance1,
+        }
+        Price::find()
+            .select_only()
+            .column_as(price::Column::Balance0, QueryAs::Balance0)
+            .column_as(price::Column::Balance1, QueryAs::Balance1)
+            .filter(price::Column::ProposalId.eq(proposal_id))
+            .order_by(price::Column::Ts, Order::Desc)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //提案全部交易,按区块顺序
+    pub async fn read_proposal_trades(&self, proposal_id: u64) -> BoxedResult<Vec<trades::Model>> {
+        Trades::find()
+            .filter(trades::Column::ProposalId.eq(proposal_id))
+            .order_by(trades::Column::Block, Order::Asc)
+            .order_by(trades::Column::LogIndex, Order::Asc)
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_proposal_transfers(
+        &self,
+        proposal_id: u64,
+    ) -> BoxedResult<Vec<transfers::Model>> {
+        Transfers::find()
+            .filter(transfers::Column::ProposalId.eq(proposal_id))
+            .order_by(transfers::Column::Block, Order::Asc)
+            .order_by(transfers::Column::LogIndex, Order::Asc)
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn read_pnl(
+        &self,
+        proposal_id: Option<u64>,
+        account: Option<String>,
+    ) -> BoxedResult<Vec<pnl::Model>> {
+        Pnl::find()
+            .filter(
+                Condition::all()
+                    .add_option(proposal_id.map(|id| pnl::Column::ProposalId.eq(id)))
+                    .add_option(account.map(|account| pnl::Column::Account.eq(account))),
+            )
+            .order_by(pnl::Column::ProposalId, Order::Desc)
+            .order_by(pnl::Column::TokenIndex, Order::Asc)
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /*
+        读取物化的排行榜,sort为已校验的排序字段
+    */
+    pub async fn read_leaderboard(
+        &self,
+        kind: String,
+        period: String,
+        sort: &str,
+        count: usize,
+        page: usize,
+    ) -> BoxedResult<(usize, Vec<LeaderboardRow>)> {
+        let total = self
+            .pool
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"SELECT COUNT(*) AS `total` FROM `leaderboard` WHERE `kind` = ? AND `period` = ?;"#,
+                vec![kind.clone().into(), period.clone().into()],
+            ))
+            .await?
+            .map(|row| row.try_get::<i64>("", "total"))
+            .transpose()?
+            .unwrap_or_default();
+        let list = LeaderboardRow::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+                SELECT `account`,
+                    CAST(`volume` AS CHAR) AS `volume`, CAST(`pnl` AS CHAR) AS `pnl`,
+                    CAST(`proposals` AS UNSIGNED) AS `proposals`, CAST(`liquidity` AS CHAR) AS `liquidity`,
+                    CAST(`estimated_fees` AS CHAR) AS `estimated_fees`, CAST(`updated_at` AS UNSIGNED) AS `updated_at`
+                    FROM `leaderboard` WHERE `kind` = ? AND `period` = ?
+                    ORDER BY `{}` DESC, `account` ASC LIMIT ? OFFSET ?;
+            "#,
+                sort
+            ),
+            vec![
+                kind.into(),
+                period.into(),
+                (count as u64).into(),
+                ((page as u64 - 1) * count as u64).into(),
+            ],
+        ))
+        .all(&self.pool)
+        .await?;
+        Ok((total as usize, list))
+    }
+
+    //时间区间内的价格 (ts, balance0, balance1),按时间顺序
+    pub async fn read_prices(
+        &self,
+        proposal_id: u64,
+        from: u64,
+        to: u64,
+    ) -> BoxedResult<Vec<(u64, String, String)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Ts,
+            Balance0,
+            Balance1,
+        }
+        Price::find()
+            .select_only()
+            .column_as(Expr::cust("CAST(`ts` AS UNSIGNED)"), QueryAs::Ts)
+            .column_as(price::Column::Balance0, QueryAs::Balance0)
+            .column_as(price::Column::Balance1, QueryAs::Balance1)
+            .filter(price::Column::ProposalId.eq(proposal_id))
+            .filter(price::Column::Ts.gte(from))
+            .filter(price::Column::Ts.lt(to))
+            .order_by(price::Column::Ts, Order::Asc)
+            .into_values::<_, QueryAs>()
+            .all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    //某时间之前的最后一条价格 (balance0, balance1)
+    pub async fn read_price_before(
+        &self,
+        proposal_id: u64,
+        ts: u64,
+    ) -> BoxedResult<Option<(String, String)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Balance0,
+            Balance1,
+        }
+        Price::find()
+            .select_only()
+            .column_as(price::Column::Balance0, QueryAs::Balance0)
+            .column_as(price::Column::Balance1, QueryAs::Balance1)
+            .filter(price::Column::ProposalId.eq(proposal_id))
+            .filter(price::Column::Ts.lt(ts))
+            .order_by(price::Column::Ts, Order::Desc)
+            .into_values::<_, QueryAs>()
+            .one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /*
+        按resolution秒分桶汇总交易额
+        返回(桶起始时间, 交易额)
+    */
+    pub async fn read_trade_volumes(
+        &self,
+        proposal_id: u64,
+        from: u64,
+        to: u64,
+        resolution: u64,
+    ) -> BoxedResult<Vec<(u64, String)>> {
+        self.pool
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT CAST(`ts` DIV ? * ? AS UNSIGNED) AS `bucket`,
+                    CAST(SUM(CAST(`amount` AS DECIMAL(65, 0))) AS CHAR) AS `volume`
+                    FROM `trades` WHERE `proposal_id` = ? AND `ts` >= ? AND `ts` < ?
+                    GROUP BY `bucket`;
+            "#,
+                vec![
+                    resolution.into(),
+                    resolution.into(),
+                    proposal_id.into(),
+                    from.into(),
+                    to.into(),
+                ],
+            ))
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get::<u64>("", "bucket")?,
+                    row.try_get::<String>("", "volume")?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DbErr>>()
+            .map_err(|e| e.into())
+    }
 }
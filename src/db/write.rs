@@ -1,13 +1,58 @@
 use sea_orm::{
     ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseBackend, Statement, TransactionTrait,
 };
+use web3::types::U256;
 
-use crate::{datas::BoxedResult, entity::*};
+use crate::{
+    datas::BoxedResult,
+    entity::*,
+    xprotocol::{ProposalInfo, ProposalResolution},
+};
 
 use super::StoreDB;
 impl StoreDB {
-    pub async fn write_block_hight(&self, chain_id: u32, hight: u64) -> BoxedResult<()> {
+    /*
+        记录检索进度,在一个事务中写入检索区块,区块hash和时间戳
+        并清理prune之前的区块hash
+    */
+    pub async fn write_checkpoint(
+        &self,
+        chain_id: u32,
+        hight: u64,
+        ts: u64,
+        hashes: Vec<(u64, String)>,
+        prune: u64,
+    ) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
+        for (number, hash) in hashes {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                INSERT INTO `block_hash`
+                    (`number`, `hash`)
+                    VALUES
+                    (?, ?)
+                    ON DUPLICATE KEY UPDATE `hash` = VALUES(`hash`);
+            "#,
+                vec![number.into(), hash.into()],
+            ))
+            .await?;
+        }
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"DELETE FROM `block_hash` WHERE `number` < ?;"#,
+            vec![prune.into()],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT INTO `block_times` (`number`, `ts`) VALUES (?, ?)
+                ON DUPLICATE KEY UPDATE `ts` = VALUES(`ts`);
+        "#,
+            vec![hight.into(), ts.into()],
+        ))
+        .await?;
         block::ActiveModel {
             id: ActiveValue::set(chain_id),
             block: ActiveValue::set(hight),
@@ -18,10 +63,143 @@ impl StoreDB {
         txn.commit().await.map_err(|e| e.into())
     }
 
+    //记录自适应的日志检索区块数
+    pub async fn write_block_step(&self, chain_id: u32, step: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        block::ActiveModel {
+            id: ActiveValue::set(chain_id),
+            block: ActiveValue::not_set(),
+            step: ActiveValue::set(step),
+        }
+        .save(&txn)
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    //区块时间戳索引,区块号和时间戳都单调递增
+    pub async fn write_block_time(&self, number: u64, ts: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT INTO `block_times` (`number`, `ts`) VALUES (?, ?)
+                ON DUPLICATE KEY UPDATE `ts` = VALUES(`ts`);
+        "#,
+            vec![number.into(), ts.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    /*
+        回滚block之后写入的数据
+        返回被删除的提案地址和受影响的提案ID
+    */
+    pub async fn rollback_to_block(
+        &self,
+        chain_id: u32,
+        block: u64,
+    ) -> BoxedResult<(Vec<String>, Vec<u64>, Vec<u64>)> {
+        let txn = self.pool.begin().await?;
+        let removed = txn
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"SELECT `address` FROM `proposals` WHERE `block` > ?;"#,
+                vec![block.into()],
+            ))
+            .await?
+            .iter()
+            .map(|row| row.try_get::<String>("", "address"))
+            .collect::<Result<Vec<String>, _>>()?;
+        let touched = txn
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `price` WHERE `block` > ?
+                UNION
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `relations` WHERE `block` > ?
+                UNION
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `trades` WHERE `block` > ?
+                UNION
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `liquidity_events` WHERE `block` > ?
+                UNION
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `transfers` WHERE `block` > ?;
+            "#,
+                vec![
+                    block.into(),
+                    block.into(),
+                    block.into(),
+                    block.into(),
+                    block.into(),
+                ],
+            ))
+            .await?
+            .iter()
+            .map(|row| row.try_get::<u64>("", "proposal_id"))
+            .collect::<Result<Vec<u64>, _>>()?;
+        //分叉点之后路由事件修改过状态,且分叉点之前已创建的提案
+        let restated = txn
+            .query_all(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                SELECT CAST(`proposal_id` AS UNSIGNED) AS `proposal_id`
+                    FROM `proposals` WHERE `state_block` > ? AND `block` <= ?;
+            "#,
+                vec![block.into(), block.into()],
+            ))
+            .await?
+            .iter()
+            .map(|row| row.try_get::<u64>("", "proposal_id"))
+            .collect::<Result<Vec<u64>, _>>()?;
+        for table in [
+            "price",
+            "relations",
+            "trades",
+            "liquidity_events",
+            "transfers",
+            "resolutions",
+            "proposals",
+        ] {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                &format!("DELETE FROM `{}` WHERE `block` > ?;", table),
+                vec![block.into()],
+            ))
+            .await?;
+        }
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"DELETE FROM `block_hash` WHERE `number` > ?;"#,
+            vec![block.into()],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"DELETE FROM `block_times` WHERE `number` > ?;"#,
+            vec![block.into()],
+        ))
+        .await?;
+        block::ActiveModel {
+            id: ActiveValue::set(chain_id),
+            block: ActiveValue::set(block),
+            step: ActiveValue::not_set(),
+        }
+        .save(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok((removed, touched, restated))
+    }
+
     pub async fn write_coins_support(
         &self,
         addr: String,
         symbol: String,
+        decimals: u8,
         flag: bool,
     ) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
@@ -29,12 +207,62 @@ impl StoreDB {
             DatabaseBackend::MySql,
             r#"
             INSERT INTO `coins` 
-                (`address`, `symbol`, `flag`) 
+                (`address`, `symbol`, `flag`, `decimals`) 
                 VALUES 
-                (?, ?, ?)
-                ON DUPLICATE KEY UPDATE `flag` = VALUES(`flag`);
+                (?, ?, ?, ?)
+                ON DUPLICATE KEY UPDATE `flag` = VALUES(`flag`), `decimals` = VALUES(`decimals`);
         "#,
-            vec![addr.into(), symbol.into(), (flag as u8).into()],
+            vec![addr.into(), symbol.into(), (flag as u8).into(), decimals.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    pub async fn write_coin_decimals(&self, addr: String, decimals: u8) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"UPDATE `coins` SET `decimals` = ? WHERE `address` = ?;"#,
+            vec![decimals.into(), addr.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    //管理员提交的币种美元价格
+    pub async fn write_token_price(&self, token: String, price: U256, ts: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT INTO `token_prices` (`token`, `price`, `updated_at`) VALUES (?, ?, ?)
+                ON DUPLICATE KEY UPDATE `price` = VALUES(`price`), `updated_at` = VALUES(`updated_at`);
+        "#,
+            vec![token.into(), price.to_string().into(), ts.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    /*
+        按币种的美元价格换算该币种下所有提案的美元交易额和TVL
+        price为1个币种对应的美元价格,按18位放大,结果同样按18位放大
+    */
+    pub async fn write_usd_values(&self, token: String, price: U256, decimals: u8) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            UPDATE `proposals`, (SELECT CAST(? AS DECIMAL(65, 0)) AS `price`,
+                CAST(CONCAT('1', REPEAT('0', ?)) AS DECIMAL(65, 0)) AS `factor`) AS `u`
+                SET `volume_usd` = FLOOR(`volume` * `u`.`price` / `u`.`factor`),
+                    `volume24_usd` = FLOOR(`volume24` * `u`.`price` / `u`.`factor`),
+                    `volume48_usd` = FLOOR(`volume48` * `u`.`price` / `u`.`factor`),
+                    `volume7d_usd` = FLOOR(`volume7d` * `u`.`price` / `u`.`factor`),
+                    `tvl_usd` = FLOOR(`liquidity` * `u`.`price` / `u`.`factor`)
+                WHERE `token` = ?;
+        "#,
+            vec![price.to_string().into(), decimals.into(), token.into()],
         ))
         .await?;
         txn.commit().await.map_err(|e| e.into())
@@ -48,8 +276,9 @@ impl StoreDB {
         category: u64,
         token: String,
         state: String,
-        liquidity: u128,
+        liquidity: String,
         times: [u64; 2],
+        block: u64,
     ) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
         let values = vec![
@@ -58,18 +287,19 @@ impl StoreDB {
             category.into(),
             token.into(),
             state.into(),
-            format!("{}", liquidity).into(),
+            liquidity.into(),
             times[0].into(),
             times[1].into(),
+            block.into(),
         ];
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"
             INSERT INTO `proposals`
                 (`proposal_id`, `address`, `category`, `token`, `state`, 
-                    `liquidity`, `create_time`, `close_time`)
+                    `liquidity`, `create_time`, `close_time`, `block`, `trades_backfilled`)
                 VALUES
-                (?, ?, ?, ?, ?, ?, ?, ?)
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, 1)
                 ON DUPLICATE KEY UPDATE
                 `address` = VALUES(`address`),
                 `category` = VALUES(`category`),
@@ -77,7 +307,47 @@ impl StoreDB {
                 `state` = VALUES(`state`),
                 `liquidity` = VALUES(`liquidity`),
                 `create_time` = VALUES(`create_time`),
-                `close_time` = VALUES(`close_time`);
+                `close_time` = VALUES(`close_time`),
+                `block` = VALUES(`block`);
+            "#,
+            values,
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    pub async fn write_proposal_info(
+        &self,
+        proposal_id: u64,
+        info: &ProposalInfo,
+    ) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        let values = vec![
+            info.title.clone().into(),
+            info.details.clone().into(),
+            info.outcomes[0].clone().into(),
+            info.outcomes[1].clone().into(),
+            format!("{}", info.fee_ratio).into(),
+            format!("{:?}", info.tokens[0]).into(),
+            format!("{:?}", info.tokens[1]).into(),
+            info.close_time.into(),
+            info.category.into(),
+            proposal_id.into(),
+        ];
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            UPDATE `proposals` SET
+                `title` = ?,
+                `details` = ?,
+                `outcome0` = ?,
+                `outcome1` = ?,
+                `fee_ratio` = ?,
+                `token1_address` = ?,
+                `token2_address` = ?,
+                `close_time` = ?,
+                `category` = ?
+                WHERE `proposal_id` = ?;
             "#,
             values,
         ))
@@ -90,67 +360,496 @@ impl StoreDB {
         proposal_id: u64,
         account: String,
         relation: String,
+        block: u64,
     ) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"
             INSERT IGNORE INTO `relations`
-                (`proposal_id`, `address`, `relations`)
+                (`proposal_id`, `address`, `relations`, `block`)
                 VALUES
-                (?, ?, ?);
+                (?, ?, ?, ?);
             "#,
-            vec![proposal_id.into(), account.into(), relation.into()],
+            vec![
+                proposal_id.into(),
+                account.into(),
+                relation.into(),
+                block.into(),
+            ],
         ))
         .await?;
         txn.commit().await.map_err(|e| e.into())
     }
 
+    /*
+        按提案合约Initialize事件记录创建者和初始流动性
+        替换创建时写入的Create关系,以事件中的创建者为准
+    */
+    pub async fn write_proposal_creation(
+        &self,
+        proposal_id: u64,
+        creater: String,
+        initial_liquidity: String,
+        block: u64,
+    ) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            UPDATE `proposals`
+                SET `creater` = ?, `initial_liquidity` = ?
+                WHERE `proposal_id` = ?;
+            "#,
+            vec![
+                creater.clone().into(),
+                initial_liquidity.into(),
+                proposal_id.into(),
+            ],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            DELETE FROM `relations`
+                WHERE `proposal_id` = ? AND `relations` = 'Create' AND `address` <> ?;
+            "#,
+            vec![proposal_id.into(), creater.clone().into()],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT IGNORE INTO `relations`
+                (`proposal_id`, `address`, `relations`, `block`)
+                VALUES
+                (?, ?, 'Create', ?);
+            "#,
+            vec![proposal_id.into(), creater.into(), block.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    /*
+        写入两个选项池的原始余额,比例在读取时计算
+        token1 token2 保留整数百分比
+    */
     pub async fn write_price(
         &self,
         proposal_id: u64,
         ts: u64,
-        tokens: [u128; 2],
+        balances: [U256; 2],
+        block: u64,
     ) -> BoxedResult<()> {
+        let total = balances[0].saturating_add(balances[1]);
+        let percent = if total.is_zero() {
+            U256::from(50)
+        } else {
+            balances[0].saturating_mul(100.into()) / total
+        };
         let txn = self.pool.begin().await?;
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"
             INSERT IGNORE INTO `price`
-                (`proposal_id`, `ts`, `token1`, `token2`)
+                (`proposal_id`, `ts`, `token1`, `token2`, `block`, `balance0`, `balance1`)
                 VALUES
-                (?, ?, ?, ?);
+                (?, ?, ?, ?, ?, ?, ?);
         "#,
             vec![
                 proposal_id.into(),
                 ts.into(),
-                format!("{}", tokens[0]).into(),
-                format!("{}", tokens[1]).into(),
+                format!("{}", percent).into(),
+                format!("{}", U256::from(100) - percent).into(),
+                block.into(),
+                format!("{}", balances[0]).into(),
+                format!("{}", balances[1]).into(),
+            ],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    //返回是否为新写入的交易
+    pub async fn write_trade(&self, trade: trades::Model) -> BoxedResult<bool> {
+        let txn = self.pool.begin().await?;
+        let res = txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT IGNORE INTO `trades`
+                (`transaction_hash`, `log_index`, `proposal_id`, `block`, `account`,
+                    `token`, `side`, `amount`, `ts`)
+                VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?, ?);
+        "#,
+            vec![
+                trade.transaction_hash.into(),
+                trade.log_index.into(),
+                trade.proposal_id.into(),
+                trade.block.into(),
+                trade.account.into(),
+                trade.token.into(),
+                trade.side.into(),
+                trade.amount.into(),
+                trade.ts.into(),
+            ],
+        ))
+        .await?;
+        txn.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /*
+        重算后整体替换提案的盈亏
+    */
+    pub async fn write_pnl(
+        &self,
+        proposal_id: u64,
+        positions: Vec<pnl::Model>,
+        realized: Vec<pnl_realized::Model>,
+        fees: Vec<lp_fees::Model>,
+    ) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        for table in ["pnl", "pnl_realized", "lp_fees"] {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                &format!("DELETE FROM `{}` WHERE `proposal_id` = ?;", table),
+                vec![proposal_id.into()],
+            ))
+            .await?;
+        }
+        for position in positions {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                INSERT INTO `pnl`
+                    (`proposal_id`, `account`, `token_index`, `quantity`, `cost`,
+                        `realized`, `bought`, `sold`, `block`)
+                    VALUES
+                    (?, ?, ?, ?, ?, ?, ?, ?, ?);
+            "#,
+                vec![
+                    position.proposal_id.into(),
+                    position.account.into(),
+                    position.token_index.into(),
+                    position.quantity.into(),
+                    position.cost.into(),
+                    position.realized.into(),
+                    position.bought.into(),
+                    position.sold.into(),
+                    position.block.into(),
+                ],
+            ))
+            .await?;
+        }
+        for sell in realized {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                INSERT INTO `pnl_realized`
+                    (`transaction_hash`, `log_index`, `proposal_id`, `account`, `token_index`,
+                        `realized`, `ts`)
+                    VALUES
+                    (?, ?, ?, ?, ?, ?, ?);
+            "#,
+                vec![
+                    sell.transaction_hash.into(),
+                    sell.log_index.into(),
+                    sell.proposal_id.into(),
+                    sell.account.into(),
+                    sell.token_index.into(),
+                    sell.realized.into(),
+                    sell.ts.into(),
+                ],
+            ))
+            .await?;
+        }
+        for fee in fees {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                r#"
+                INSERT INTO `lp_fees`
+                    (`transaction_hash`, `log_index`, `account`, `proposal_id`, `fee`, `ts`)
+                    VALUES
+                    (?, ?, ?, ?, ?, ?);
+            "#,
+                vec![
+                    fee.transaction_hash.into(),
+                    fee.log_index.into(),
+                    fee.account.into(),
+                    fee.proposal_id.into(),
+                    fee.fee.into(),
+                    fee.ts.into(),
+                ],
+            ))
+            .await?;
+        }
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    /*
+        物化某个时间窗口的排行榜,since为窗口起点,全部时间为0
+        交易者: 交易额, 窗口内卖出的已实现盈亏加上结算的兑付盈亏
+        创建者: 进入Formal的提案数(审核不通过直接结束的不计), 其提案产生的交易额
+        流动性提供者: 添加的流动性, lp_fees中按交易时份额分配的手续费(feeRatio按万分比估算)
+        金额按提案结算币种的精度统一换算为18位,精度超过18位的币种不换算
+    */
+    pub async fn write_leaderboard(&self, period: String, since: u64, now: u64) -> BoxedResult<()> {
+        let scale = r#"
+            (SELECT `p`.`proposal_id`, CAST(CONCAT('1', REPEAT('0',
+                18 - LEAST(IFNULL(`c`.`decimals`, 18), 18))) AS DECIMAL(65, 0)) AS `factor`
+                FROM `proposals` AS `p` LEFT JOIN `coins` AS `c` ON `c`.`address` = `p`.`token`)
+        "#;
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"DELETE FROM `leaderboard` WHERE `period` = ?;"#,
+            vec![period.clone().into()],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+            INSERT INTO `leaderboard` (`kind`, `period`, `account`, `volume`, `pnl`, `updated_at`)
+            SELECT 'Traders', ?, `account`, SUM(`volume` * `s`.`factor`), SUM(`pnl` * `s`.`factor`), ? FROM (
+                SELECT `proposal_id`, `account`, CAST(`amount` AS DECIMAL(65, 0)) AS `volume`, 0 AS `pnl`
+                    FROM `trades` WHERE `ts` >= ?
+                UNION ALL
+                SELECT `proposal_id`, `account`, 0, CAST(`realized` AS DECIMAL(65, 0))
+                    FROM `pnl_realized` WHERE `ts` >= ?
+                UNION ALL
+                SELECT `p`.`proposal_id`, `p`.`account`, 0,
+                    IF(`p`.`token_index` = `r`.`result`, CAST(`p`.`quantity` AS DECIMAL(65, 0)), 0)
+                        - CAST(`p`.`cost` AS DECIMAL(65, 0))
+                    FROM `pnl` AS `p` JOIN `resolutions` AS `r` ON `r`.`proposal_id` = `p`.`proposal_id`
+                    WHERE `r`.`ts` >= ?
+            ) AS `t` JOIN {} AS `s` ON `s`.`proposal_id` = `t`.`proposal_id`
+            GROUP BY `account`;
+        "#,
+                scale
+            ),
+            vec![
+                period.clone().into(),
+                now.into(),
+                since.into(),
+                since.into(),
+                since.into(),
             ],
         ))
         .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+            INSERT INTO `leaderboard` (`kind`, `period`, `account`, `proposals`, `volume`, `updated_at`)
+            SELECT 'Creators', ?, `r`.`address`,
+                SUM(IF(`p`.`state` <> 'Original' AND `p`.`audit_state` <> 'NotPassed'
+                    AND `p`.`create_time` >= ?, 1, 0)),
+                IFNULL(SUM(`v`.`volume` * `s`.`factor`), 0), ?
+                FROM `relations` AS `r`
+                JOIN `proposals` AS `p` ON `p`.`proposal_id` = `r`.`proposal_id`
+                JOIN {} AS `s` ON `s`.`proposal_id` = `r`.`proposal_id`
+                LEFT JOIN (
+                    SELECT `proposal_id`, SUM(CAST(`amount` AS DECIMAL(65, 0))) AS `volume`
+                        FROM `trades` WHERE `ts` >= ? GROUP BY `proposal_id`
+                ) AS `v` ON `v`.`proposal_id` = `r`.`proposal_id`
+                WHERE `r`.`relations` = 'Create'
+                GROUP BY `r`.`address`;
+        "#,
+                scale
+            ),
+            vec![period.clone().into(), since.into(), now.into(), since.into()],
+        ))
+        .await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+            INSERT INTO `leaderboard` (`kind`, `period`, `account`, `liquidity`, `estimated_fees`, `updated_at`)
+            SELECT 'Lps', ?, `account`, SUM(`added` * `s`.`factor`), SUM(`fees` * `s`.`factor`), ? FROM (
+                SELECT `proposal_id`, `account`, CAST(`amount` AS DECIMAL(65, 0)) AS `added`, 0 AS `fees`
+                    FROM `liquidity_events` WHERE `side` = 'Add' AND `ts` >= ?
+                UNION ALL
+                SELECT `proposal_id`, `account`, 0, CAST(`fee` AS DECIMAL(65, 0))
+                    FROM `lp_fees` WHERE `ts` >= ?
+            ) AS `t` JOIN {} AS `s` ON `s`.`proposal_id` = `t`.`proposal_id`
+            GROUP BY `account`;
+        "#,
+                scale
+            ),
+            vec![period.into(), now.into(), since.into(), since.into()],
+        ))
+        .await?;
         txn.commit().await.map_err(|e| e.into())
     }
 
-    pub async fn write_volume24(&self, proposal_id: u64, volume24: String) -> BoxedResult<()> {
+    pub async fn write_liquidity_event(&self, event: liquidity_events::Model) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
-        let values = vec![proposal_id.into(), volume24.into()];
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"
-            INSERT INTO `proposals`
-                (`proposal_id`, `volume24`)
+            INSERT IGNORE INTO `liquidity_events`
+                (`transaction_hash`, `log_index`, `proposal_id`, `block`, `account`,
+                    `side`, `amount`, `shares`, `ts`)
                 VALUES
-                (?, ?)
+                (?, ?, ?, ?, ?, ?, ?, ?, ?);
+        "#,
+            vec![
+                event.transaction_hash.into(),
+                event.log_index.into(),
+                event.proposal_id.into(),
+                event.block.into(),
+                event.account.into(),
+                event.side.into(),
+                event.amount.into(),
+                event.shares.into(),
+                event.ts.into(),
+            ],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    pub async fn write_transfer(&self, transfer: transfers::Model) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT IGNORE INTO `transfers`
+                (`transaction_hash`, `log_index`, `proposal_id`, `token`, `token_index`,
+                    `sender`, `receiver`, `amount`, `block`)
+                VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?, ?);
+        "#,
+            vec![
+                transfer.transaction_hash.into(),
+                transfer.log_index.into(),
+                transfer.proposal_id.into(),
+                transfer.token.into(),
+                transfer.token_index.into(),
+                transfer.sender.into(),
+                transfer.receiver.into(),
+                transfer.amount.into(),
+                transfer.block.into(),
+            ],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    pub async fn write_resolution(
+        &self,
+        proposal_id: u64,
+        address: String,
+        resolution: &ProposalResolution,
+    ) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"
+            INSERT INTO `resolutions`
+                (`proposal_id`, `address`, `result`, `end_balance_token0`, `end_balance_token1`,
+                    `end_total_supply`, `block`, `ts`)
+                VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?)
                 ON DUPLICATE KEY UPDATE
-                `volume24` = VALUES(`volume24`);
+                `address` = VALUES(`address`),
+                `result` = VALUES(`result`),
+                `end_balance_token0` = VALUES(`end_balance_token0`),
+                `end_balance_token1` = VALUES(`end_balance_token1`),
+                `end_total_supply` = VALUES(`end_total_supply`),
+                `block` = VALUES(`block`),
+                `ts` = VALUES(`ts`);
+        "#,
+            vec![
+                proposal_id.into(),
+                address.into(),
+                resolution.result.into(),
+                resolution.end_balance_token0.clone().into(),
+                resolution.end_balance_token1.clone().into(),
+                resolution.end_total_supply.clone().into(),
+                resolution.block.into(),
+                resolution.ts.into(),
+            ],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    /*
+        按区块区间[from, to]内的交易流水重算窗口交易额
+        column为proposals表的交易额字段
+    */
+    pub async fn rebuild_window_volume(&self, column: &str, from: u64, to: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+            UPDATE `proposals` AS `p` LEFT JOIN (
+                SELECT `proposal_id`, SUM(CAST(`amount` AS DECIMAL(65, 0))) AS `volume`
+                    FROM `trades` WHERE `block` >= ? AND `block` <= ? GROUP BY `proposal_id`
+            ) AS `t` ON `t`.`proposal_id` = `p`.`proposal_id`
+                SET `p`.`{0}` = IFNULL(`t`.`volume`, 0);
             "#,
+                column
+            ),
+            vec![from.into(), to.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    //新交易累加到窗口交易额
+    pub async fn add_window_volume(
+        &self,
+        proposal_id: u64,
+        amount: String,
+        columns: Vec<&str>,
+    ) -> BoxedResult<()> {
+        let sets = columns
+            .iter()
+            .map(|column| format!("`{0}` = `{0}` + CAST(? AS DECIMAL(65, 0))", column))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut values = columns
+            .iter()
+            .map(|_| amount.clone().into())
+            .collect::<Vec<sea_orm::Value>>();
+        values.push(proposal_id.into());
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!("UPDATE `proposals` SET {} WHERE `proposal_id` = ?;", sets),
             values,
         ))
         .await?;
         txn.commit().await.map_err(|e| e.into())
     }
 
+    //窗口起点前移,减去区块区间[from, to)内的交易
+    pub async fn age_window_volume(&self, column: &str, from: u64, to: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            &format!(
+                r#"
+            UPDATE `proposals` AS `p` JOIN (
+                SELECT `proposal_id`, SUM(CAST(`amount` AS DECIMAL(65, 0))) AS `volume`
+                    FROM `trades` WHERE `block` >= ? AND `block` < ? GROUP BY `proposal_id`
+            ) AS `t` ON `t`.`proposal_id` = `p`.`proposal_id`
+                SET `p`.`{0}` = GREATEST(`p`.`{0}` - `t`.`volume`, 0);
+            "#,
+                column
+            ),
+            vec![from.into(), to.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
     pub async fn write_volume(&self, proposal_id: u64, volume: String) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
         let values = vec![proposal_id.into(), volume.into()];
@@ -189,22 +888,37 @@ impl StoreDB {
         txn.commit().await.map_err(|e| e.into())
     }
 
+    //提案创建以来的交易流水已补齐
+    pub async fn write_trades_backfilled(&self, proposal_id: u64) -> BoxedResult<()> {
+        let txn = self.pool.begin().await?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            r#"UPDATE `proposals` SET `trades_backfilled` = 1 WHERE `proposal_id` = ?;"#,
+            vec![proposal_id.into()],
+        ))
+        .await?;
+        txn.commit().await.map_err(|e| e.into())
+    }
+
+    //state_block为状态变化的区块,回滚时据此恢复分叉点的状态
     pub async fn write_proposal_state(
         &self,
         proposal_id: String,
         state: String,
+        block: u64,
     ) -> BoxedResult<()> {
         let txn = self.pool.begin().await?;
-        let values = vec![proposal_id.into(), state.into()];
+        let values = vec![proposal_id.into(), state.into(), block.into()];
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::MySql,
             r#"
             INSERT INTO `proposals`
-                (`proposal_id`, `state`)
+                (`proposal_id`, `state`, `state_block`)
                 VALUES
-                (?, ?)
+                (?, ?, ?)
                 ON DUPLICATE KEY UPDATE
-                `state` = VALUES(`state`);
+                `state` = VALUES(`state`),
+                `state_block` = VALUES(`state_block`);
             "#,
             values,
         ))
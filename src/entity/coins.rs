@@ -9,6 +9,7 @@ pub struct Model {
     pub address: String,
     pub symbol: String,
     pub flag: u8,
+    pub decimals: u8,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -4,7 +4,17 @@ pub mod prelude;
 
 pub mod banner;
 pub mod block;
+pub mod block_hash;
+pub mod block_times;
 pub mod coins;
+pub mod liquidity_events;
+pub mod lp_fees;
+pub mod pnl;
+pub mod pnl_realized;
 pub mod price;
 pub mod proposals;
 pub mod relations;
+pub mod resolutions;
+pub mod token_prices;
+pub mod trades;
+pub mod transfers;
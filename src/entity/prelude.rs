@@ -2,7 +2,15 @@
 
 pub use super::banner::Entity as Banner;
 pub use super::block::Entity as Block;
+pub use super::block_hash::Entity as BlockHash;
+pub use super::block_times::Entity as BlockTimes;
 pub use super::coins::Entity as Coins;
+pub use super::liquidity_events::Entity as LiquidityEvents;
+pub use super::pnl::Entity as Pnl;
 pub use super::price::Entity as Price;
 pub use super::proposals::Entity as Proposals;
 pub use super::relations::Entity as Relations;
+pub use super::resolutions::Entity as Resolutions;
+pub use super::token_prices::Entity as TokenPrices;
+pub use super::trades::Entity as Trades;
+pub use super::transfers::Entity as Transfers;
@@ -10,6 +10,9 @@ pub struct Model {
     pub ts: u64,
     pub token1: u64,
     pub token2: u64,
+    pub block: u64,
+    pub balance0: String,
+    pub balance1: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -20,6 +20,23 @@ pub struct Model {
     pub state: String,
     pub volume: String,
     pub volume24: String,
+    pub volume48: String,
+    pub volume7d: String,
+    pub volume_usd: String,
+    pub volume24_usd: String,
+    pub volume48_usd: String,
+    pub volume7d_usd: String,
+    pub tvl_usd: String,
+    pub block: u64,
+    pub title: String,
+    pub details: String,
+    pub outcome0: String,
+    pub outcome1: String,
+    pub fee_ratio: String,
+    pub creater: String,
+    pub initial_liquidity: String,
+    pub state_block: u64,
+    pub trades_backfilled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
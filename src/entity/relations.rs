@@ -15,6 +15,7 @@ pub struct Model {
         column_type = "Custom(\"ENUM ('Liquidity','Create','Trade')\".to_owned())"
     )]
     pub relations: String,
+    pub block: u64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
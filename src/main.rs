@@ -4,7 +4,9 @@ mod db;
 mod entity;
 mod xprotocol;
 
-use crate::{actors::block::BlockActor, datas::handle::Handlers};
+use crate::{
+    actors::block::BlockActor, datas::handle::Handlers, xprotocol::transport::Failover,
+};
 use actix::Actor;
 use actix_web::{self, middleware::Logger, App, HttpServer};
 use dotenv::dotenv;
@@ -17,8 +19,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let bind_address = format!("{}:{}", config.server.host, config.server.port);
 
-    let data = datas::init_app_data(&config.server.database_url, config.contract.chain_id).await?;
-    BlockActor::new((*data).clone(), &config.contract)?.start();
+    let rpc = Failover::new(&config.contract.rpc, config.contract.rpc_max_lag)?;
+    let data = datas::init_app_data(
+        &config.server.database_url,
+        config.contract.chain_id,
+        config.server.admin_token.clone(),
+        rpc,
+    )
+    .await?;
+    BlockActor::new((*data).clone(), &config.contract)
+        .await?
+        .start();
 
     HttpServer::new(move || {
         App::new()
@@ -0,0 +1,336 @@
+use std::sync::OnceLock;
+
+use web3::{
+    ethabi::{self, Token},
+    signing::keccak256,
+    types::{H160, U256},
+};
+
+use crate::datas::BoxedSyncResult;
+
+use super::ProposalInfo;
+
+/*
+    编译时由src/res的abi生成,每个合约包含
+    functions: 函数的输入编码和返回值解码
+    events: 事件签名和日志解码,abi与日志不一致时返回错误
+    logs: 事件结构,字段名为abi参数名
+*/
+//createProposal有8个参数
+#[allow(clippy::too_many_arguments)]
+pub mod factory {
+    #[allow(dead_code)]
+    #[derive(ethabi_derive::EthabiContract)]
+    #[ethabi_contract_options(path = "src/res/factory_abi.json")]
+    struct Abi;
+}
+
+pub mod router {
+    #[allow(dead_code)]
+    #[derive(ethabi_derive::EthabiContract)]
+    #[ethabi_contract_options(path = "src/res/router_abi.json")]
+    struct Abi;
+}
+
+pub mod proposal {
+    #[allow(dead_code)]
+    #[derive(ethabi_derive::EthabiContract)]
+    #[ethabi_contract_options(path = "src/res/proposal_abi.json")]
+    struct Abi;
+}
+
+pub mod outcome_token {
+    #[allow(dead_code)]
+    #[derive(ethabi_derive::EthabiContract)]
+    #[ethabi_contract_options(path = "src/res/protocolErc20_abi.json")]
+    struct Abi;
+}
+
+//提案合约proposalInfo的返回值
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProposalInfoView {
+    pub close_time: U256,
+    pub category: U256,
+    pub token0: H160,
+    pub token1: H160,
+    pub outcome0: String,
+    pub outcome1: String,
+    pub owner: H160,
+    pub proposal: H160,
+    pub state: U256,
+    pub found_market: H160,
+    pub fee_ratio: U256,
+    pub initial_liquidity: U256,
+    pub title: String,
+    pub details: String,
+}
+
+impl ProposalInfoView {
+    pub fn encode_input() -> Vec<u8> {
+        proposal::functions::proposal_info::encode_input()
+    }
+
+    pub fn decode_output(output: &[u8]) -> BoxedSyncResult<Self> {
+        let (
+            close_time,
+            category,
+            token0,
+            token1,
+            outcome0,
+            outcome1,
+            owner,
+            proposal,
+            state,
+            found_market,
+            fee_ratio,
+            initial_liquidity,
+            title,
+            details,
+        ) = proposal::functions::proposal_info::decode_output(output)?;
+        Ok(Self {
+            close_time,
+            category,
+            token0,
+            token1,
+            outcome0,
+            outcome1,
+            owner,
+            proposal,
+            state,
+            found_market,
+            fee_ratio,
+            initial_liquidity,
+            title,
+            details,
+        })
+    }
+
+    pub fn info(&self) -> BoxedSyncResult<ProposalInfo> {
+        Ok(ProposalInfo {
+            title: self.title.clone(),
+            details: self.details.clone(),
+            outcomes: [self.outcome0.clone(), self.outcome1.clone()],
+            close_time: u64::try_from(self.close_time)
+                .map_err(|_| format!("close time {} overflow", self.close_time))?,
+            category: u64::try_from(self.category)
+                .map_err(|_| format!("category {} overflow", self.category))?,
+            fee_ratio: self.fee_ratio,
+            tokens: [self.token0, self.token1],
+        })
+    }
+}
+
+//交易输入的解码器,abi中的函数和选择器只在第一次使用时读取
+struct CallDecoder {
+    function: ethabi::Function,
+    selector: [u8; 4],
+}
+
+impl CallDecoder {
+    fn get(
+        cell: &'static OnceLock<Self>,
+        abi: &[u8],
+        name: &str,
+    ) -> BoxedSyncResult<&'static Self> {
+        if let Some(decoder) = cell.get() {
+            return Ok(decoder);
+        }
+        let function = ethabi::Contract::load(abi)?.function(name)?.clone();
+        let types = function
+            .inputs
+            .iter()
+            .map(|param| param.kind.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        let hash = keccak256(format!("{}({})", name, types).as_bytes());
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&hash[..4]);
+        Ok(cell.get_or_init(|| Self { function, selector }))
+    }
+
+    /*
+        去掉函数选择器后按abi解码
+        不是该函数的调用或输入不足4字节时返回错误
+    */
+    fn decode(&self, input: &[u8]) -> BoxedSyncResult<Vec<Token>> {
+        let input = input
+            .strip_prefix(&self.selector)
+            .ok_or_else(|| format!("not {} call", self.function.name))?;
+        Ok(self.function.decode_input(input)?)
+    }
+}
+
+//工厂合约createProposal的输入
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateProposalCall {
+    pub title: String,
+    pub details: String,
+    pub outcome: [String; 2],
+    pub close_time: U256,
+    pub category: U256,
+    pub found_market: H160,
+    pub amount: U256,
+    pub fee_ratio: U256,
+}
+
+impl CreateProposalCall {
+    pub fn decode(input: &[u8]) -> BoxedSyncResult<Self> {
+        static DECODER: OnceLock<CallDecoder> = OnceLock::new();
+        let abi = include_bytes!("../res/factory_abi.json");
+        let decoder = CallDecoder::get(&DECODER, abi, "createProposal")?;
+        match decoder.decode(input)?.as_slice() {
+            [Token::String(title), Token::String(details), Token::FixedArray(outcome), Token::Uint(close_time), Token::Uint(category), Token::Address(found_market), Token::Uint(amount), Token::Uint(fee_ratio)] =>
+            {
+                let outcome = match outcome.as_slice() {
+                    [Token::String(outcome0), Token::String(outcome1)] => {
+                        [outcome0.clone(), outcome1.clone()]
+                    }
+                    _ => return Err("createProposal outcome mismatch".into()),
+                };
+                Ok(Self {
+                    title: title.clone(),
+                    details: details.clone(),
+                    outcome,
+                    close_time: *close_time,
+                    category: *category,
+                    found_market: *found_market,
+                    amount: *amount,
+                    fee_ratio: *fee_ratio,
+                })
+            }
+            _ => Err("createProposal input mismatch".into()),
+        }
+    }
+
+    //选项代币来自CreateProposal日志
+    pub fn info(&self, tokens: [H160; 2]) -> BoxedSyncResult<ProposalInfo> {
+        Ok(ProposalInfo {
+            title: self.title.clone(),
+            details: self.details.clone(),
+            outcomes: self.outcome.clone(),
+            close_time: u64::try_from(self.close_time)
+                .map_err(|_| format!("close time {} overflow", self.close_time))?,
+            category: u64::try_from(self.category)
+                .map_err(|_| format!("category {} overflow", self.category))?,
+            fee_ratio: self.fee_ratio,
+            tokens,
+        })
+    }
+}
+
+//路由合约acceptProposalUploadResult的输入
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadResultCall {
+    pub proposal: H160,
+    pub result: U256,
+}
+
+impl UploadResultCall {
+    pub fn decode(input: &[u8]) -> BoxedSyncResult<Self> {
+        static DECODER: OnceLock<CallDecoder> = OnceLock::new();
+        let abi = include_bytes!("../res/router_abi.json");
+        let decoder = CallDecoder::get(&DECODER, abi, "acceptProposalUploadResult")?;
+        match decoder.decode(input)?.as_slice() {
+            [Token::Address(proposal), Token::Uint(result)] => Ok(Self {
+                proposal: *proposal,
+                result: *result,
+            }),
+            _ => Err("acceptProposalUploadResult input mismatch".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use web3::types::{Bytes, H160, U256};
+
+    use super::{factory, router, CreateProposalCall, ProposalInfoView, UploadResultCall};
+
+    //按abi编码的eth_call返回值和交易输入样例
+    fn fixture(name: &str) -> Vec<u8> {
+        let calls: HashMap<String, Bytes> =
+            serde_json::from_str(include_str!("../res/fixtures/calls.json")).unwrap();
+        calls[name].0.clone()
+    }
+
+    fn address(n: u64) -> H160 {
+        H160::from_low_u64_be(n)
+    }
+
+    #[test]
+    fn decode_proposal_info() {
+        let view = ProposalInfoView::decode_output(&fixture("proposalInfo")).unwrap();
+        assert_eq!(view.owner, address(0xc3));
+        assert_eq!(view.proposal, address(0xb1));
+        assert_eq!(view.found_market, address(0x55d398));
+        assert_eq!(view.initial_liquidity, U256::exp10(20));
+        let info = view.info().unwrap();
+        assert_eq!(info.title, "Will it rain");
+        assert_eq!(info.outcomes, ["Yes".to_string(), "No".to_string()]);
+        assert_eq!(info.close_time, 1660000000);
+        assert_eq!(info.category, 1);
+        assert_eq!(info.fee_ratio, U256::from(20));
+        assert_eq!(info.tokens, [address(0xa1), address(0xa2)]);
+
+        let output = fixture("proposalInfo");
+        assert!(ProposalInfoView::decode_output(&output[..output.len() / 2]).is_err());
+
+        //超出u64的字段返回错误
+        let view = ProposalInfoView {
+            close_time: U256::MAX,
+            ..view
+        };
+        assert!(view.info().is_err());
+    }
+
+    #[test]
+    fn decode_create_proposal_call() {
+        let call = CreateProposalCall::decode(&fixture("createProposal")).unwrap();
+        assert_eq!(call.details, "Resolves yes if it rains");
+        assert_eq!(call.found_market, address(0x55d398));
+        assert_eq!(call.amount, U256::exp10(20));
+        let info = call.info([address(0xa1), address(0xa2)]).unwrap();
+        assert_eq!(info.title, "Will it rain");
+        assert_eq!(info.close_time, 1660000000);
+
+        //输入不足4字节或不是createProposal调用
+        assert!(CreateProposalCall::decode(&[0x12, 0x34]).is_err());
+        assert!(CreateProposalCall::decode(&fixture("acceptProposalUploadResult")).is_err());
+    }
+
+    #[test]
+    fn decode_upload_result_call() {
+        let call = UploadResultCall::decode(&fixture("acceptProposalUploadResult")).unwrap();
+        assert_eq!(call.proposal, address(0xb1));
+        assert_eq!(call.result, U256::one());
+        assert!(UploadResultCall::decode(&[]).is_err());
+    }
+
+    //解码器的选择器与生成的合约模块一致
+    #[test]
+    fn decode_generated_calls() {
+        let input = router::functions::accept_proposal_upload_result::encode_input(
+            address(0xb1),
+            U256::one(),
+        );
+        let call = UploadResultCall::decode(&input).unwrap();
+        assert_eq!(call.proposal, address(0xb1));
+        assert_eq!(call.result, U256::one());
+
+        let input = factory::functions::create_proposal::encode_input(
+            "Will it rain",
+            "Resolves yes if it rains",
+            ["Yes".to_string(), "No".to_string()],
+            1660000000u64,
+            1u64,
+            address(0x55d398),
+            U256::exp10(20),
+            20u64,
+        );
+        let call = CreateProposalCall::decode(&input).unwrap();
+        assert_eq!(call.amount, U256::exp10(20));
+        assert!(UploadResultCall::decode(&input).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use web3::{types::BlockId, Web3};
+
+use crate::datas::{data::AppData, BoxedSyncResult};
+
+use super::transport::Failover;
+
+pub struct BlockTime;
+
+impl BlockTime {
+    //区块时间戳,优先读取已记录的索引,没有则从链上读取并记录
+    pub async fn timestamp(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        number: u64,
+    ) -> BoxedSyncResult<u64> {
+        let store = &data.store;
+        if let Some(ts) = store
+            .read_block_time(number)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(ts);
+        }
+        let block = web3
+            .eth()
+            .block(BlockId::Number(number.into()))
+            .await?
+            .ok_or("empty block")?;
+        let ts = block.timestamp.as_u64();
+        store
+            .write_block_time(number, ts)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(ts)
+    }
+
+    /*
+        时间戳不早于ts的第一个区块,不超过hight
+        先用已记录的区块缩小区间,再二分查找,查找过程中的区块都会记录
+    */
+    pub async fn block_at(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        ts: u64,
+        hight: u64,
+    ) -> BoxedSyncResult<u64> {
+        let (before, after) = data
+            .store
+            .read_block_time_bounds(ts, hight)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut low = before.map(|number| number + 1).unwrap_or(0);
+        let mut high = after.unwrap_or(hight);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if Self::timestamp(data, web3, mid).await? < ts {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low.min(hight))
+    }
+
+    //以hight的区块时间为终点,向前seconds秒的窗口起点区块
+    pub async fn window_start(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        hight: u64,
+        seconds: u64,
+    ) -> BoxedSyncResult<u64> {
+        let ts = Self::timestamp(data, web3, hight).await?;
+        Self::block_at(data, web3, ts.saturating_sub(seconds), hight).await
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use web3::{
+    ethabi,
+    types::{Log, H256},
+};
+
+use crate::datas::BoxedSyncResult;
+
+use super::abi::{factory, outcome_token, proposal, router};
+
+//发出日志的合约类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventSource {
+    Factory,
+    Router,
+    Proposal,
+    OutcomeToken,
+}
+
+impl EventSource {
+    const SOURCES: [Self; 4] = [
+        Self::Factory,
+        Self::Router,
+        Self::Proposal,
+        Self::OutcomeToken,
+    ];
+
+    fn abi(&self) -> &'static [u8] {
+        match self {
+            Self::Factory => include_bytes!("../res/factory_abi.json"),
+            Self::Router => include_bytes!("../res/router_abi.json"),
+            Self::Proposal => include_bytes!("../res/proposal_abi.json"),
+            Self::OutcomeToken => include_bytes!("../res/protocolErc20_abi.json"),
+        }
+    }
+}
+
+//需要处理的事件,名称与abi中的事件名一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    SupportMarkets,
+    SettlementCurrencyPaused,
+    CreateProposal,
+    AcceptStateFormalPrediction,
+    AcceptProposalEnd,
+    Initialize,
+    Buy,
+    Sell,
+    AddLiquidity,
+    RemoveLiquidity,
+    Transfer,
+    Burn,
+}
+
+// 事件登记表,新增事件在此登记,并在对应合约的处理函数中处理
+const HANDLED: [(EventSource, EventKind); 12] = [
+    (EventSource::Factory, EventKind::SupportMarkets),
+    (EventSource::Factory, EventKind::SettlementCurrencyPaused),
+    (EventSource::Factory, EventKind::CreateProposal),
+    (EventSource::Router, EventKind::AcceptStateFormalPrediction),
+    (EventSource::Router, EventKind::AcceptProposalEnd),
+    (EventSource::Proposal, EventKind::Initialize),
+    (EventSource::Proposal, EventKind::Buy),
+    (EventSource::Proposal, EventKind::Sell),
+    (EventSource::Proposal, EventKind::AddLiquidity),
+    (EventSource::Proposal, EventKind::RemoveLiquidity),
+    (EventSource::OutcomeToken, EventKind::Transfer),
+    (EventSource::OutcomeToken, EventKind::Burn),
+];
+
+//解码后的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    SupportMarkets(factory::logs::SupportMarkets),
+    SettlementCurrencyPaused(factory::logs::SettlementCurrencyPaused),
+    CreateProposal(factory::logs::CreateProposal),
+    AcceptStateFormalPrediction(router::logs::AcceptStateFormalPrediction),
+    AcceptProposalEnd(router::logs::AcceptProposalEnd),
+    Initialize(proposal::logs::Initialize),
+    Buy(proposal::logs::Buy),
+    Sell(proposal::logs::Sell),
+    AddLiquidity(proposal::logs::AddLiquidity),
+    RemoveLiquidity(proposal::logs::RemoveLiquidity),
+    Transfer(outcome_token::logs::Transfer),
+    Burn(outcome_token::logs::Burn),
+}
+
+impl Event {
+    //按abi生成的结构解码,参数与abi不一致时返回错误
+    fn parse(kind: EventKind, raw_log: ethabi::RawLog) -> ethabi::Result<Self> {
+        Ok(match kind {
+            EventKind::SupportMarkets => {
+                Self::SupportMarkets(factory::events::support_markets::parse_log(raw_log)?)
+            }
+            EventKind::SettlementCurrencyPaused => Self::SettlementCurrencyPaused(
+                factory::events::settlement_currency_paused::parse_log(raw_log)?,
+            ),
+            EventKind::CreateProposal => {
+                Self::CreateProposal(factory::events::create_proposal::parse_log(raw_log)?)
+            }
+            EventKind::AcceptStateFormalPrediction => Self::AcceptStateFormalPrediction(
+                router::events::accept_state_formal_prediction::parse_log(raw_log)?,
+            ),
+            EventKind::AcceptProposalEnd => {
+                Self::AcceptProposalEnd(router::events::accept_proposal_end::parse_log(raw_log)?)
+            }
+            EventKind::Initialize => {
+                Self::Initialize(proposal::events::initialize::parse_log(raw_log)?)
+            }
+            EventKind::Buy => Self::Buy(proposal::events::buy::parse_log(raw_log)?),
+            EventKind::Sell => Self::Sell(proposal::events::sell::parse_log(raw_log)?),
+            EventKind::AddLiquidity => {
+                Self::AddLiquidity(proposal::events::add_liquidity::parse_log(raw_log)?)
+            }
+            EventKind::RemoveLiquidity => {
+                Self::RemoveLiquidity(proposal::events::remove_liquidity::parse_log(raw_log)?)
+            }
+            EventKind::Transfer => {
+                Self::Transfer(outcome_token::events::transfer::parse_log(raw_log)?)
+            }
+            EventKind::Burn => Self::Burn(outcome_token::events::burn::parse_log(raw_log)?),
+        })
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::SupportMarkets(_) => EventKind::SupportMarkets,
+            Self::SettlementCurrencyPaused(_) => EventKind::SettlementCurrencyPaused,
+            Self::CreateProposal(_) => EventKind::CreateProposal,
+            Self::AcceptStateFormalPrediction(_) => EventKind::AcceptStateFormalPrediction,
+            Self::AcceptProposalEnd(_) => EventKind::AcceptProposalEnd,
+            Self::Initialize(_) => EventKind::Initialize,
+            Self::Buy(_) => EventKind::Buy,
+            Self::Sell(_) => EventKind::Sell,
+            Self::AddLiquidity(_) => EventKind::AddLiquidity,
+            Self::RemoveLiquidity(_) => EventKind::RemoveLiquidity,
+            Self::Transfer(_) => EventKind::Transfer,
+            Self::Burn(_) => EventKind::Burn,
+        }
+    }
+
+    //事件参数中的proposalId,超出u64的视为没有
+    pub fn proposal_id(&self) -> Option<u64> {
+        let proposal_id = match self {
+            Self::CreateProposal(event) => event.proposal_id,
+            Self::AcceptStateFormalPrediction(event) => event.proposal_id,
+            Self::AcceptProposalEnd(event) => event.proposal_id,
+            Self::Initialize(event) => event.proposal_id,
+            Self::AddLiquidity(event) => event.proposal_id,
+            Self::RemoveLiquidity(event) => event.proposal_id,
+            _ => return None,
+        };
+        u64::try_from(proposal_id).ok()
+    }
+}
+
+/*
+    按(合约类型, topic0)分发日志,启动时从src/res的abi构建一次
+    abi中有但未登记的事件忽略,abi中没有的topic计数并记录日志
+*/
+pub struct EventRegistry {
+    handled: HashMap<(EventSource, H256), EventKind>,
+    ignored: HashSet<(EventSource, H256)>,
+    unknown: RwLock<HashMap<(EventSource, H256), u64>>,
+}
+
+impl EventRegistry {
+    pub fn load() -> BoxedSyncResult<Self> {
+        let mut handled = HashMap::new();
+        let mut ignored = HashSet::new();
+        for source in EventSource::SOURCES {
+            let contract = ethabi::Contract::load(source.abi())?;
+            for event in contract.events().filter(|event| !event.anonymous) {
+                let kind = HANDLED
+                    .iter()
+                    .find(|(s, kind)| *s == source && format!("{:?}", kind) == event.name)
+                    .map(|(_, kind)| *kind);
+                match kind {
+                    Some(kind) => {
+                        handled.insert((source, event.signature()), kind);
+                    }
+                    None => {
+                        ignored.insert((source, event.signature()));
+                    }
+                }
+            }
+        }
+        //登记的事件必须在abi中存在
+        for (source, kind) in HANDLED {
+            if !handled.iter().any(|((s, _), k)| *s == source && *k == kind) {
+                return Err(format!("event {:?} not found in {:?} abi", kind, source).into());
+            }
+        }
+        Ok(Self {
+            handled,
+            ignored,
+            unknown: RwLock::new(HashMap::new()),
+        })
+    }
+
+    //某类合约需要处理的事件签名
+    pub fn topics(&self, source: EventSource) -> Vec<H256> {
+        self.handled
+            .keys()
+            .filter(|(s, _)| *s == source)
+            .map(|(_, topic)| *topic)
+            .collect()
+    }
+
+    /*
+        按topic0找到登记的事件并解码
+        未登记或解码失败返回None,解码失败和未知topic记录日志
+    */
+    pub fn decode(&self, source: EventSource, log: &Log) -> Option<Event> {
+        let topic = log.topics.first().cloned().unwrap_or_default();
+        let kind = match self.handled.get(&(source, topic)) {
+            Some(handled) => handled,
+            None => {
+                if !self.ignored.contains(&(source, topic)) {
+                    self.count_unknown(source, topic, log);
+                }
+                return None;
+            }
+        };
+        let raw_log = ethabi::RawLog {
+            topics: log.topics.clone(),
+            data: log.data.0.clone(),
+        };
+        match Event::parse(*kind, raw_log) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                log::error!(
+                    "decode {:?} from {:?} in {:?} error: {:?}",
+                    kind,
+                    log.address,
+                    log.transaction_hash,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    fn count_unknown(&self, source: EventSource, topic: H256, log: &Log) {
+        if let Ok(mut unknown) = self.unknown.write() {
+            let count = unknown.entry((source, topic)).or_default();
+            if *count == 0 {
+                log::warn!(
+                    "unknown topic {:?} from {:?} {:?}",
+                    topic,
+                    source,
+                    log.address
+                );
+            }
+            *count += 1;
+        }
+    }
+
+    //未知topic的累计次数
+    pub fn unknown(&self) -> Vec<(EventSource, H256, u64)> {
+        self.unknown
+            .read()
+            .map(|unknown| {
+                unknown
+                    .iter()
+                    .map(|((source, topic), count)| (*source, *topic, *count))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use web3::{
+        ethabi,
+        types::{Bytes, Log, H160, U256},
+    };
+
+    use super::{Event, EventKind, EventRegistry, EventSource};
+    use crate::xprotocol::abi::{outcome_token, proposal};
+
+    //按abi编码的样例日志,格式与eth_getLogs返回一致
+    fn fixture(name: &str) -> Log {
+        let logs: HashMap<String, Log> =
+            serde_json::from_str(include_str!("../res/fixtures/logs.json")).unwrap();
+        logs[name].clone()
+    }
+
+    fn address(n: u64) -> H160 {
+        H160::from_low_u64_be(n)
+    }
+
+    fn raw_log(log: &Log) -> ethabi::RawLog {
+        ethabi::RawLog {
+            topics: log.topics.clone(),
+            data: log.data.0.clone(),
+        }
+    }
+
+    #[test]
+    fn decode_factory_events() {
+        let registry = EventRegistry::load().unwrap();
+        let event = registry.decode(EventSource::Factory, &fixture("SupportMarkets"));
+        match event {
+            Some(Event::SupportMarkets(event)) => assert_eq!(event.token, address(0x55d398)),
+            other => panic!("unexpected {:?}", other),
+        }
+        let event = registry.decode(EventSource::Factory, &fixture("SettlementCurrencyPaused"));
+        match event {
+            Some(Event::SettlementCurrencyPaused(event)) => {
+                assert_eq!(event.token, address(0x55d398))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let event = registry
+            .decode(EventSource::Factory, &fixture("CreateProposal"))
+            .unwrap();
+        assert_eq!(event.kind(), EventKind::CreateProposal);
+        assert_eq!(event.proposal_id(), Some(12));
+        match event {
+            Event::CreateProposal(event) => {
+                assert_eq!(event.token0, address(0xa1));
+                assert_eq!(event.token1, address(0xa2));
+                assert_eq!(event.pair, address(0xb1));
+                assert_eq!(event.create_time, U256::from(1650000000));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_router_events() {
+        let registry = EventRegistry::load().unwrap();
+        for (name, kind) in [
+            (
+                "AcceptStateFormalPrediction",
+                EventKind::AcceptStateFormalPrediction,
+            ),
+            ("AcceptProposalEnd", EventKind::AcceptProposalEnd),
+        ] {
+            let event = registry
+                .decode(EventSource::Router, &fixture(name))
+                .unwrap();
+            assert_eq!(event.kind(), kind);
+            assert_eq!(event.proposal_id(), Some(12));
+        }
+    }
+
+    #[test]
+    fn decode_proposal_events() {
+        let registry = EventRegistry::load().unwrap();
+        let event = registry
+            .decode(EventSource::Proposal, &fixture("Initialize"))
+            .unwrap();
+        assert_eq!(event.kind(), EventKind::Initialize);
+        assert_eq!(event.proposal_id(), Some(12));
+        match event {
+            Event::Initialize(event) => {
+                assert_eq!(event.proposal, address(0xb1));
+                assert_eq!(event.creater, address(0xc3));
+                assert_eq!(event.amount, U256::exp10(20));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match registry.decode(EventSource::Proposal, &fixture("Buy")) {
+            Some(Event::Buy(event)) => {
+                assert_eq!(event.token, address(0xa1));
+                assert_eq!(event.account, address(0xc1));
+                assert_eq!(event.amount, U256::exp10(18));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match registry.decode(EventSource::Proposal, &fixture("Sell")) {
+            Some(Event::Sell(event)) => {
+                assert_eq!(event.token, address(0xa2));
+                assert_eq!(event.account, address(0xc1));
+                assert_eq!(event.amount, U256::exp10(17));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match registry.decode(EventSource::Proposal, &fixture("AddLiquidity")) {
+            Some(Event::AddLiquidity(event)) => {
+                assert_eq!(event.account, address(0xc2));
+                assert_eq!(event.amount, U256::exp10(19));
+                assert_eq!(event.proposal_id, U256::from(12));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match registry.decode(EventSource::Proposal, &fixture("RemoveLiquidity")) {
+            Some(Event::RemoveLiquidity(event)) => {
+                assert_eq!(event.account, address(0xc2));
+                assert_eq!(event.amount, U256::exp10(18));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        //提案事件按签名检索
+        assert_eq!(registry.topics(EventSource::Proposal).len(), 5);
+    }
+
+    #[test]
+    fn decode_outcome_transfer() {
+        let registry = EventRegistry::load().unwrap();
+        match registry.decode(EventSource::OutcomeToken, &fixture("Transfer")) {
+            Some(Event::Transfer(event)) => {
+                assert_eq!(event.from, address(0xc1));
+                assert_eq!(event.to, address(0xc2));
+                assert_eq!(event.value, U256::exp10(18));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match registry.decode(EventSource::OutcomeToken, &fixture("Burn")) {
+            Some(Event::Burn(event)) => {
+                assert_eq!(event.from, address(0xc1));
+                assert_eq!(event.value, U256::exp10(17));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ignored_and_unknown_topics() {
+        let registry = EventRegistry::load().unwrap();
+        //abi中有但未登记的事件忽略,不计数
+        assert!(registry
+            .decode(EventSource::OutcomeToken, &fixture("Approval"))
+            .is_none());
+        assert!(registry.unknown().is_empty());
+        //其他合约的事件不属于工厂abi
+        let log = fixture("Buy");
+        assert!(registry.decode(EventSource::Factory, &log).is_none());
+        assert!(registry.decode(EventSource::Factory, &log).is_none());
+        assert_eq!(
+            registry.unknown(),
+            vec![(EventSource::Factory, log.topics[0], 2)]
+        );
+    }
+
+    #[test]
+    fn mismatched_logs_fail() {
+        let registry = EventRegistry::load().unwrap();
+        //数据被截断
+        let mut log = fixture("Buy");
+        log.data = Bytes(log.data.0[..64].to_vec());
+        assert!(proposal::events::buy::parse_log(raw_log(&log)).is_err());
+        assert!(registry.decode(EventSource::Proposal, &log).is_none());
+        //缺少indexed参数
+        let mut log = fixture("Transfer");
+        log.topics.truncate(2);
+        assert!(outcome_token::events::transfer::parse_log(raw_log(&log)).is_err());
+        assert!(registry.decode(EventSource::OutcomeToken, &log).is_none());
+        //topic0与事件不一致
+        let log = fixture("Sell");
+        assert!(proposal::events::buy::parse_log(raw_log(&log)).is_err());
+    }
+
+    /*
+        用部署的合约检查abi,需要能访问节点:
+        FIXTURE_RPC(默认CONTRACT.RPC)上[FIXTURE_FROM, FIXTURE_TO]区间内工厂、路由、提案和选项代币的日志
+        登记的事件都能解码,没有未知topic,区间内创建的提案proposalInfo和createProposal输入都能解码
+        每种事件的第一条日志和调用数据写入target/fixtures,可替换src/res/fixtures中的样例
+        cargo test deployed -- --ignored
+    */
+    #[test]
+    #[ignore]
+    fn decode_deployed_logs() {
+        use std::fs;
+
+        use web3::{
+            types::{BlockId, BlockNumber, CallRequest, TransactionId},
+            Web3,
+        };
+
+        use crate::xprotocol::{
+            abi::{CreateProposalCall, ProposalInfoView},
+            filter::LogFilter,
+            transport::Failover,
+        };
+
+        dotenv::dotenv().ok();
+        let env = |key: &str| std::env::var(key).ok();
+        let rpc = env("FIXTURE_RPC")
+            .or_else(|| env("CONTRACT.RPC"))
+            .expect("FIXTURE_RPC not set");
+        let factory = env("CONTRACT.FACTORY").unwrap().parse::<H160>().unwrap();
+        let router = env("CONTRACT.ROUTER").unwrap().parse::<H160>().unwrap();
+        let web3 = Web3::new(Failover::new(&rpc, 5).unwrap());
+        let registry = EventRegistry::load().unwrap();
+
+        actix_web::rt::System::new().block_on(async {
+            let head = web3.eth().block_number().await.unwrap().as_u64();
+            let to = env("FIXTURE_TO").map_or(head, |to| to.parse().unwrap());
+            let from =
+                env("FIXTURE_FROM").map_or(to.saturating_sub(10_000), |f| f.parse().unwrap());
+            let topics = registry.topics(EventSource::Proposal);
+            let mut logs = LogFilter::logs(&web3, from, to, &[factory, router], &topics)
+                .await
+                .unwrap();
+            let mut logs_fixture = HashMap::<String, Log>::new();
+            let mut calls_fixture = HashMap::<String, Bytes>::new();
+            let mut tokens = Vec::<H160>::new();
+            for log in logs.iter() {
+                let source = match log.address {
+                    addr if addr == factory => EventSource::Factory,
+                    addr if addr == router => EventSource::Router,
+                    _ => EventSource::Proposal,
+                };
+                let event = registry.decode(source, log);
+                if registry.handled.contains_key(&(source, log.topics[0])) {
+                    assert!(event.is_some(), "decode {:?} failed", log);
+                }
+                if let Some(Event::CreateProposal(create)) = &event {
+                    let block = BlockId::Number(BlockNumber::Latest);
+                    let call = CallRequest {
+                        to: Some(create.pair),
+                        data: Some(Bytes(ProposalInfoView::encode_input())),
+                        ..Default::default()
+                    };
+                    let output = web3.eth().call(call, Some(block)).await.unwrap();
+                    let view = ProposalInfoView::decode_output(&output.0).unwrap();
+                    tokens.extend(view.info().unwrap().tokens);
+                    calls_fixture
+                        .entry("proposalInfo".to_string())
+                        .or_insert(output);
+                    let tx = TransactionId::Hash(log.transaction_hash.unwrap());
+                    let tx = web3.eth().transaction(tx).await.unwrap().unwrap();
+                    //经多签等合约创建时输入不是createProposal
+                    if tx.to == Some(factory) {
+                        CreateProposalCall::decode(&tx.input.0).unwrap();
+                        calls_fixture
+                            .entry("createProposal".to_string())
+                            .or_insert(tx.input);
+                    }
+                }
+                if let Some(event) = event {
+                    let kind = format!("{:?}", event.kind());
+                    logs_fixture.entry(kind).or_insert_with(|| log.clone());
+                }
+            }
+            logs.clear();
+            if !tokens.is_empty() {
+                logs = LogFilter::logs(&web3, from, to, &tokens, &[])
+                    .await
+                    .unwrap();
+            }
+            for log in logs.iter() {
+                let event = registry.decode(EventSource::OutcomeToken, log);
+                if registry
+                    .handled
+                    .contains_key(&(EventSource::OutcomeToken, log.topics[0]))
+                {
+                    assert!(event.is_some(), "decode {:?} failed", log);
+                }
+                if let Some(event) = event {
+                    let kind = format!("{:?}", event.kind());
+                    logs_fixture.entry(kind).or_insert_with(|| log.clone());
+                }
+            }
+            assert!(
+                registry.unknown().is_empty(),
+                "unknown topics {:?}",
+                registry.unknown()
+            );
+
+            fs::create_dir_all("target/fixtures").unwrap();
+            let json = serde_json::to_string_pretty(&logs_fixture).unwrap();
+            fs::write("target/fixtures/logs.json", json).unwrap();
+            let json = serde_json::to_string_pretty(&calls_fixture).unwrap();
+            fs::write("target/fixtures/calls.json", json).unwrap();
+        });
+    }
+}
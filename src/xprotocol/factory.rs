@@ -1,10 +1,9 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use futures::{future::LocalBoxFuture, FutureExt};
 use web3::{
     contract::{Contract, Options},
-    ethabi, transports,
-    types::{Log, TransactionId, H160},
+    types::{BlockId, Bytes, CallRequest, Log, TransactionId, H160, U256},
     Web3,
 };
 
@@ -12,95 +11,34 @@ use crate::{
     actors::ws::SubOpCode,
     datas::{
         data::AppData,
-        handle::{ProposalRelation, ProposalState},
+        handle::{ProposalRelation, ProposalState, MAX_DECIMALS},
         BoxedSyncResult,
     },
-    xprotocol::ProposalStatus,
+    xprotocol::{ProposalInfo, ProposalStatus},
 };
 
-use super::{BoxFn, ModuleTest, XProtocol};
+use super::{
+    abi::{factory, CreateProposalCall},
+    events::{Event, EventSource},
+    proposals::Proposal,
+    transport::Failover,
+    ModuleTest,
+};
 
 pub struct Factory {
     data: Arc<AppData>,
-    web3: Arc<Web3<transports::Http>>,
+    web3: Arc<Web3<Failover>>,
+    //读取提案合约失败时是否解析交易输入
+    calldata_fallback: bool,
 }
 
 impl Factory {
-    pub fn new(data: Arc<AppData>, web3: Arc<Web3<transports::Http>>) -> Self {
-        Self { data, web3 }
-    }
-
-    fn parse_log_address(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<H160> {
-        // event SettlementCurrencyPaused(address token);
-        // event SupportMarkets(address token);
-
-        let abi_log = XProtocol::parse_log(raw_log, contract, name)?;
-        XProtocol::get_index(&abi_log.params, 0)?
-            .value
-            .into_address()
-            .ok_or_else(|| "convert to address error".into())
-    }
-
-    fn parse_log_create_proposal(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-    ) -> BoxedSyncResult<(u64, H160, u64)> {
-        // event CreateProposal(uint proposalId, address token0, address token1, address pair);
-
-        let abi_log = XProtocol::parse_log(raw_log, contract, "CreateProposal")?;
-        let params = abi_log.params;
-
-        let proposal_id = XProtocol::get_index(&params, 0)?
-            .value
-            .into_uint()
-            .ok_or("convert to uint error")?
-            .as_u64();
-
-        let proposal = XProtocol::get_index(&params, 3)?
-            .value
-            .into_address()
-            .ok_or("convert to address error")?;
-
-        let time = XProtocol::get_index(&params, 4)?
-            .value
-            .into_uint()
-            .ok_or("convert to uint error")?
-            .as_u64();
-        Ok((proposal_id, proposal, time))
-    }
-
-    fn parse_create_proposal_input(
-        contract: &ethabi::Contract,
-        input: &[u8],
-    ) -> BoxedSyncResult<(u64, u64, H160, u128)> {
-        // function createProposal(string memory title, string memory details, string[2] memory outcome, uint closeTime, uint category,
-        //     address foundMarket, uint256 amount, uint256 feeRatio)
-        let function = contract.function("createProposal")?;
-
-        let params = function.decode_input(input)?;
-        let close_time = XProtocol::get_index(&params, 3)?
-            .into_uint()
-            .ok_or("into uint error")?
-            .as_u64();
-
-        let category = XProtocol::get_index(&params, 4)?
-            .into_uint()
-            .ok_or("into uint error")?
-            .as_u64();
-
-        let found = XProtocol::get_index(&params, 5)?
-            .into_address()
-            .ok_or("into address error")?;
-
-        let number = XProtocol::get_index(&params, 6)?
-            .into_uint()
-            .ok_or("into uint error")?
-            .as_u128();
-        Ok((close_time, category, found, number))
+    pub fn new(data: Arc<AppData>, web3: Arc<Web3<Failover>>, calldata_fallback: bool) -> Self {
+        Self {
+            data,
+            web3,
+            calldata_fallback,
+        }
     }
 
     //修改数据库支持币种
@@ -114,15 +52,21 @@ impl Factory {
         let symbol: String = erc20
             .query("symbol", (), None, Options::default(), None)
             .await?;
+        let decimals: u8 = erc20
+            .query("decimals", (), None, Options::default(), None)
+            .await?;
+        if decimals > MAX_DECIMALS {
+            return Err(format!("coin {} decimals {} unsupported", addr, decimals).into());
+        }
 
         self.data //写入data
-            .insert_support(addr.clone(), symbol.clone(), flag)
+            .insert_support(addr.clone(), symbol.clone(), decimals, flag)
             .map_err(|e| e.to_string())?;
 
         if let Err(e) = self //写入db
             .data
             .store
-            .write_coins_support(addr.clone(), symbol, flag)
+            .write_coins_support(addr.clone(), symbol, decimals, flag)
             .await
         {
             log::error!("write coins support error: {:?}", e);
@@ -135,120 +79,179 @@ impl Factory {
         Ok(())
     }
 
-    fn get_support_test_fn() -> HashMap<bool, BoxFn<H160>> {
-        let mut box_fns = HashMap::<bool, BoxFn<H160>>::new();
-        box_fns.insert(
-            true,
-            Box::new(|raw_log, contract| {
-                Self::parse_log_address(raw_log, contract, "SupportMarkets")
-            }),
-        );
-        box_fns.insert(
-            false,
-            Box::new(|raw_log, contract| {
-                Self::parse_log_address(raw_log, contract, "SettlementCurrencyPaused")
-            }),
-        );
-        box_fns
+    /*
+        旧库的币种没有精度,从合约补齐
+        单个币种读取失败或精度超出MAX_DECIMALS时记录日志,保留原精度继续下一个
+    */
+    pub async fn refresh_decimals(data: &Arc<AppData>, web3: &Arc<Web3<Failover>>) {
+        for addr in data.get_coins() {
+            if let Err(e) = Self::refresh_coin_decimals(data, web3, addr.clone()).await {
+                log::error!("refresh coin {} decimals error: {:?}", addr, e);
+            }
+        }
     }
 
-    //factory日志更新
-    async fn with_factory(&self, log: &Log) -> BoxedSyncResult<()> {
-        //构建raw_log
-        let raw_log = ethabi::RawLog {
-            topics: log.topics.clone(),
-            data: log.data.0.clone(),
-        };
-        //根据abi构建contract
-        let contract = ethabi::Contract::load(include_bytes!("../res/factory_abi.json").as_ref())?;
-        //更新支持和不支持币种
-        let box_fns = Self::get_support_test_fn();
-        for (flag, test_fn) in box_fns.iter() {
-            //修改支持币种
-            if let Ok(addr) = test_fn(&raw_log, &contract) {
-                if let Err(e) = self.with_address(addr, *flag).await {
-                    //写入或修改
-                    log::error!("with address error: {:?}", e);
-                }
-            }
+    async fn refresh_coin_decimals(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        addr: String,
+    ) -> BoxedSyncResult<()> {
+        let erc20 = Contract::from_json(
+            web3.eth(),
+            addr.parse::<H160>()?,
+            include_bytes!("../res/erc20_abi.json"),
+        )?;
+        let decimals: u8 = erc20
+            .query("decimals", (), None, Options::default(), None)
+            .await?;
+        if decimals > MAX_DECIMALS {
+            return Err(format!("decimals {} unsupported", decimals).into());
         }
-        //
-        //日志解析新提案event的信息  提案ID 提案地址 创建时间
-        if let Ok((proposal_id, proposal_add, create_time)) =
-            Self::parse_log_create_proposal(&raw_log, &contract)
-        {
-            //读取交易hash，获取创建提案
-            self.update_new_proposal(
-                &contract,
-                TransactionId::Hash(log.transaction_hash.ok_or("txid is none")?),
-                proposal_id,
-                proposal_add,
-                create_time,
-            )
+        data.insert_decimals(addr.clone(), decimals)
+            .map_err(|e| e.to_string())?;
+        data.store
+            .write_coin_decimals(addr, decimals)
             .await
-        } else {
-            Ok(())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    //工厂合约在block已分配的提案ID
+    pub async fn currency_proposal_id(
+        web3: &Arc<Web3<Failover>>,
+        factory: H160,
+        block: u64,
+    ) -> BoxedSyncResult<u64> {
+        let input = factory::functions::currency_proposal_id::encode_input();
+        let call = CallRequest {
+            to: Some(factory),
+            data: Some(Bytes(input)),
+            ..Default::default()
+        };
+        let output = web3
+            .eth()
+            .call(call, Some(BlockId::Number(block.into())))
+            .await?;
+        let proposal_id = factory::functions::currency_proposal_id::decode_output(&output.0)?;
+        Ok(u64::try_from(proposal_id)
+            .map_err(|_| format!("proposal id {} overflow", proposal_id))?)
+    }
+
+    //factory日志更新
+    async fn with_factory(&self, log: &Log, event: Event) -> BoxedSyncResult<()> {
+        match event {
+            //更新支持和不支持币种
+            Event::SupportMarkets(event) => self.with_address(event.token, true).await,
+            Event::SettlementCurrencyPaused(event) => self.with_address(event.token, false).await,
+            //日志解析新提案event的信息  提案ID 提案地址 创建时间
+            Event::CreateProposal(event) => {
+                let proposal_id = u64::try_from(event.proposal_id)
+                    .map_err(|_| format!("proposal id {} overflow", event.proposal_id))?;
+                let create_time = u64::try_from(event.create_time)
+                    .map_err(|_| format!("create time {} overflow", event.create_time))?;
+                self.update_new_proposal(
+                    log,
+                    proposal_id,
+                    event.pair,
+                    (create_time, [event.token0, event.token1]),
+                )
+                .await
+            }
+            _ => Ok(()),
         }
     }
 
     /*
-        读取factory 某一区块的日志
-        获取创建提案输入参数和event
+        解析交易输入中的createProposal,只适用于直接调用工厂合约的交易
+        返回提案信息 结算币种 初始流动性 创建者
+    */
+    async fn creation_from_calldata(
+        &self,
+        log: &Log,
+        tokens: [H160; 2],
+    ) -> BoxedSyncResult<(ProposalInfo, H160, U256, H160)> {
+        let transaction_id = TransactionId::Hash(log.transaction_hash.ok_or("txid is none")?);
+        let transaction = self
+            .web3
+            .eth()
+            .transaction(transaction_id)
+            .await?
+            .ok_or("get transaction return nill")?;
+        let call = CreateProposalCall::decode(&transaction.input.0)?;
+        Ok((
+            call.info(tokens)?,
+            call.found_market,
+            call.amount,
+            transaction.from.ok_or("from address none")?,
+        ))
+    }
+
+    /*
+        在CreateProposal日志的区块读取提案合约的proposalInfo和creater
+        读取失败且开启calldata_fallback时解析交易输入
         写入数据库proposal,relation,price
         写入actix data
     */
     async fn update_new_proposal(
         &self,
-        contract: &ethabi::Contract,
-        transaction_id: TransactionId,
+        log: &Log,
         proposal_id: u64,
         proposal_add: H160,
-        create_time: u64,
+        (create_time, tokens): (u64, [H160; 2]),
     ) -> BoxedSyncResult<()> {
         // 数据库写入
         let store = &self.data.store;
-        //日志解析新提案event的信息  提案ID 提案地址 创建时间
-        // let (proposal_id, proposal_add, create_time) =
-        //     Self::parse_log_create_proposal(raw_log, contract)?;
-        //获取交易
-        let transaction = self
-            .web3
-            .eth()
-            .transaction(transaction_id)
-            .await?
-            .ok_or("get transaction return nill")?;
-        //解析交易输入数据    提案结束时间  提案类别  结算币种地址 初始流动性数量
-        let (close_time, category, token, number) =
-            Self::parse_create_proposal_input(contract, &transaction.input.0[4..])?;
+        let block = log.block_number.ok_or("block number is none")?.as_u64();
+        let block_id = BlockId::Hash(log.block_hash.ok_or("block hash empty")?);
+        //提案信息  结算币种地址 初始流动性数量 创建者
+        let (mut info, token, number, owner) =
+            match Proposal::creation_info(&self.web3, proposal_add, block_id).await {
+                Ok(creation) => creation,
+                Err(e) if self.calldata_fallback => {
+                    log::warn!("read proposal {} info error: {:?}", proposal_id, e);
+                    self.creation_from_calldata(log, tokens).await?
+                }
+                Err(e) => return Err(e),
+            };
+        //选项代币以日志为准
+        info.tokens = tokens;
         //写入proposal
         let state = format!("{:?}", ProposalState::Original);
         store
             .write_proposals(
                 proposal_id,
                 format!("{:?}", proposal_add),
-                category,
+                info.category,
                 format!("{:?}", token),
                 state,
-                number,
-                [create_time, close_time],
+                format!("{}", number),
+                [create_time, info.close_time],
+                block,
             )
             .await
             .map_err(|e| e.to_string())?;
+        //写入提案标题 详情 选项 手续费率 选项代币
+        store
+            .write_proposal_info(proposal_id, &info)
+            .await
+            .map_err(|e| e.to_string())?;
 
-        let owner = format!("{:?}", transaction.from.ok_or("from address none")?);
+        let owner = format!("{:?}", owner);
         let relation = format!("{:?}", ProposalRelation::Create);
         // 写入relation
         store
-            .write_relation(proposal_id, owner, relation)
+            .write_relation(proposal_id, owner, relation, block)
             .await
             .map_err(|e| e.to_string())?;
-        // 写入price
+        // 写入price,创建时两个选项池相等
         store
-            .write_price(proposal_id, create_time, [50, 50])
+            .write_price(proposal_id, create_time, [U256::one(), U256::one()], block)
             .await
             .map_err(|e| e.to_string())?;
         // data写入
-        self.data.insert_proposal(proposal_add, proposal_id);
+        self.data
+            .insert_proposal(proposal_add, proposal_id, format!("{:?}", token));
+        self.data.insert_outcome_tokens(proposal_id, info.tokens);
         self.data
             .set_proposal_state(proposal_id, ProposalState::Original);
         self.data.push_to_client(
@@ -261,7 +264,15 @@ impl Factory {
 }
 
 impl ModuleTest for Factory {
-    fn with_fn<'a>(&'a self, log: &'a Log) -> LocalBoxFuture<'a, BoxedSyncResult<()>> {
-        self.with_factory(log).boxed()
+    fn source(&self) -> EventSource {
+        EventSource::Factory
+    }
+
+    fn with_fn<'a>(
+        &'a self,
+        log: &'a Log,
+        event: Event,
+    ) -> LocalBoxFuture<'a, BoxedSyncResult<()>> {
+        self.with_factory(log, event).boxed()
     }
 }
@@ -0,0 +1,51 @@
+use futures::future::try_join_all;
+use web3::{
+    error::Error,
+    types::{FilterBuilder, Log, H160, H256},
+    Web3,
+};
+
+use super::transport::Failover;
+
+// 单次请求的地址数上限,节点通常拒绝过长的地址列表
+const ADDRESS_CHUNK: usize = 200;
+
+pub struct LogFilter;
+
+impl LogFilter {
+    /*
+        [from_block, to_block]区间内的日志,按区块和日志序号排序
+        提案合约的日志按EventRegistry登记的事件签名检索,不限地址,由调用方按AppData的提案匹配
+        其余地址分片检索,提案数量增加时请求的地址数不会无限增长
+    */
+    pub async fn logs(
+        web3: &Web3<Failover>,
+        from_block: u64,
+        to_block: u64,
+        addresses: &[H160],
+        topics: &[H256],
+    ) -> Result<Vec<Log>, Error> {
+        let builder = || {
+            FilterBuilder::default()
+                .from_block(from_block.into())
+                .to_block(to_block.into())
+        };
+        let mut filters = addresses
+            .chunks(ADDRESS_CHUNK)
+            .map(|chunk| builder().address(chunk.to_vec()).build())
+            .collect::<Vec<_>>();
+        if !topics.is_empty() {
+            filters.push(
+                builder()
+                    .topics(Some(topics.to_vec()), None, None, None)
+                    .build(),
+            );
+        }
+        let fetched =
+            try_join_all(filters.into_iter().map(|filter| web3.eth().logs(filter))).await?;
+        let mut logs = fetched.into_iter().flatten().collect::<Vec<Log>>();
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        logs.dedup_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+}
@@ -1,21 +1,36 @@
+pub(crate) mod abi;
+pub(crate) mod blocks;
+pub(crate) mod events;
 pub(crate) mod factory;
+pub(crate) mod filter;
+pub(crate) mod oracle;
+pub(crate) mod outcome;
+pub(crate) mod pnl;
 pub(crate) mod proposals;
 pub(crate) mod router;
+pub(crate) mod subscribe;
+pub(crate) mod transport;
+pub(crate) mod volume;
 
 use futures::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
-use web3::{ethabi, types::{Log, H160}};
+use web3::types::{Log, H160, U256};
 
-use crate::datas::{BoxedSyncResult, handle::ProposalState};
+use crate::{
+    datas::{handle::ProposalState, BoxedSyncResult},
+    entity::resolutions,
+};
+
+use self::events::{Event, EventRegistry, EventSource};
 
-pub type BoxFn<T> =
-    Box<dyn Fn(&ethabi::RawLog, &ethabi::Contract) -> BoxedSyncResult<T> + Send + Sync>;
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ProposalStatus {
     pub proposal_id: u64,
     pub address: String,
     pub state: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<ProposalResolution>,
 }
 
 impl ProposalStatus {
@@ -24,33 +39,91 @@ impl ProposalStatus {
             proposal_id,
             address: format!("{:?}", address),
             state: state as u8,
+            resolution: None,
         }
     }
+
+    pub fn with_resolution(mut self, resolution: Option<ProposalResolution>) -> Self {
+        self.resolution = resolution;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalResolution {
+    pub result: u64,
+    pub end_balance_token0: String,
+    pub end_balance_token1: String,
+    pub end_total_supply: String,
+    pub block: u64,
+    pub ts: u64,
 }
-pub struct XProtocol;
-
-impl XProtocol {
-    pub fn get_index<T>(array: &[T], i: usize) -> BoxedSyncResult<T>
-    where
-        T: Clone,
-    {
-        array
-            .get(i)
-            .cloned()
-            .ok_or_else(|| format!("{} index out of bound", i).into())
+
+impl From<resolutions::Model> for ProposalResolution {
+    fn from(model: resolutions::Model) -> Self {
+        Self {
+            result: model.result,
+            end_balance_token0: model.end_balance_token0,
+            end_balance_token1: model.end_balance_token1,
+            end_total_supply: model.end_total_supply,
+            block: model.block,
+            ts: model.ts,
+        }
     }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProposalInfo {
+    pub title: String,
+    pub details: String,
+    pub outcomes: [String; 2],
+    pub close_time: u64,
+    pub category: u64,
+    pub fee_ratio: U256,
+    pub tokens: [H160; 2],
+}
 
-    pub fn parse_log(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<ethabi::Log> {
-        let event = contract.event(name)?;
-        let abi_event: ethabi::Event = event.clone();
-        abi_event.parse_log(raw_log.clone()).map_err(|e| e.into())
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingEvent {
+    pub proposal_id: Option<u64>,
+    pub address: String,
+    pub event: String,
+    pub block: u64,
+    pub transaction_hash: String,
+    pub tentative: bool,
+}
+
+impl PendingEvent {
+    /*
+        未确认日志: 根据登记的事件解析事件名和提案ID
+        提案合约的日志用已知的提案ID,factory和router的日志读取参数proposalId
+    */
+    pub fn from_log(
+        registry: &EventRegistry,
+        source: EventSource,
+        log: &Log,
+        proposal_id: Option<u64>,
+    ) -> Option<Self> {
+        let event = registry.decode(source, log)?;
+        let proposal_id = proposal_id.or_else(|| event.proposal_id());
+        Some(Self {
+            proposal_id,
+            address: format!("{:?}", log.address),
+            event: format!("{:?}", event.kind()),
+            block: log.block_number?.as_u64(),
+            transaction_hash: format!("{:?}", log.transaction_hash?),
+            tentative: true,
+        })
     }
 }
 
 pub trait ModuleTest {
-    fn with_fn<'a>(&'a self, log: &'a Log) -> LocalBoxFuture<'a, BoxedSyncResult<()>>;
+    fn source(&self) -> EventSource;
+    fn with_fn<'a>(
+        &'a self,
+        log: &'a Log,
+        event: Event,
+    ) -> LocalBoxFuture<'a, BoxedSyncResult<()>>;
 }
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use futures::{future::LocalBoxFuture, FutureExt};
+use web3::{
+    contract::{Contract, Options},
+    types::{H160, U256},
+    Web3,
+};
+
+use crate::datas::{data::AppData, handle::MAX_DECIMALS, BoxedSyncResult};
+
+use super::transport::Failover;
+
+// 美元价格和美元金额的小数位数
+pub const USD_DECIMALS: u8 = 18;
+
+/*
+    币种的美元价格,1个币种对应的美元数按USD_DECIMALS放大
+    没有价格时返回None
+*/
+pub trait PriceOracle {
+    fn usd_price<'a>(&'a self, token: &'a str)
+        -> LocalBoxFuture<'a, BoxedSyncResult<Option<U256>>>;
+}
+
+//管理员通过接口提交的价格
+pub struct AdminOracle {
+    data: Arc<AppData>,
+}
+
+impl AdminOracle {
+    pub fn new(data: Arc<AppData>) -> Self {
+        Self { data }
+    }
+
+    async fn read_price(&self, token: &str) -> BoxedSyncResult<Option<U256>> {
+        let price = self
+            .data
+            .store
+            .read_token_price(token.to_string())
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(price.and_then(|price| U256::from_dec_str(&price).ok()))
+    }
+}
+
+impl PriceOracle for AdminOracle {
+    fn usd_price<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, BoxedSyncResult<Option<U256>>> {
+        self.read_price(token).boxed_local()
+    }
+}
+
+//链上定价合约,getPrice(token)按合约的decimals()放大
+pub struct ContractOracle {
+    contract: Contract<Failover>,
+    decimals: u8,
+}
+
+impl ContractOracle {
+    //合约精度创建时读取一次,超出MAX_DECIMALS的合约不支持
+    pub async fn new(web3: &Arc<Web3<Failover>>, address: H160) -> BoxedSyncResult<Self> {
+        let contract = Contract::from_json(
+            web3.eth(),
+            address,
+            include_bytes!("../res/oracle_abi.json"),
+        )?;
+        let decimals: u8 = contract
+            .query("decimals", (), None, Options::default(), None)
+            .await?;
+        if decimals > MAX_DECIMALS {
+            return Err(format!("oracle decimals {} unsupported", decimals).into());
+        }
+        Ok(Self { contract, decimals })
+    }
+
+    async fn query_price(&self, token: &str) -> BoxedSyncResult<Option<U256>> {
+        let token = token.parse::<H160>()?;
+        let price: U256 = self
+            .contract
+            .query("getPrice", (token,), None, Options::default(), None)
+            .await?;
+        if price.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some(Self::to_usd_decimals(price, self.decimals)))
+    }
+
+    //换算到USD_DECIMALS位,decimals不超过MAX_DECIMALS
+    fn to_usd_decimals(price: U256, decimals: u8) -> U256 {
+        if decimals <= USD_DECIMALS {
+            price.saturating_mul(U256::exp10((USD_DECIMALS - decimals) as usize))
+        } else {
+            price / U256::exp10((decimals - USD_DECIMALS) as usize)
+        }
+    }
+}
+
+impl PriceOracle for ContractOracle {
+    fn usd_price<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, BoxedSyncResult<Option<U256>>> {
+        self.query_price(token).boxed_local()
+    }
+}
+
+pub struct Oracle;
+
+impl Oracle {
+    /*
+        从预言机刷新所有币种的美元价格,再重算提案的美元交易额和TVL
+        取价失败的币种沿用上一次的价格
+    */
+    pub async fn refresh(data: &Arc<AppData>, oracle: &dyn PriceOracle) -> BoxedSyncResult<()> {
+        for token in data.get_coins() {
+            match oracle.usd_price(&token).await {
+                Ok(Some(price)) => data
+                    .insert_usd_price(token, price)
+                    .map_err(|e| e.to_string())?,
+                Ok(None) => log::warn!("no usd price for {}", token),
+                Err(e) => log::error!("read usd price of {} error: {:?}", token, e),
+            }
+        }
+        Self::apply(data).await
+    }
+
+    //按缓存的价格重算,交易额和流动性变化后调用
+    pub async fn apply(data: &Arc<AppData>) -> BoxedSyncResult<()> {
+        for (token, price) in data.get_usd_prices() {
+            let decimals = data.get_decimals(&token);
+            data.store
+                .write_usd_values(token, price, decimals)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::U256;
+
+    use super::ContractOracle;
+    use crate::datas::handle::MAX_DECIMALS;
+
+    #[test]
+    fn price_scaled_to_usd_decimals() {
+        assert_eq!(
+            ContractOracle::to_usd_decimals(U256::from(150), 2),
+            U256::from(15) * U256::exp10(17)
+        );
+        assert_eq!(
+            ContractOracle::to_usd_decimals(U256::from(7), 18),
+            U256::from(7)
+        );
+        assert_eq!(
+            ContractOracle::to_usd_decimals(U256::exp10(30), 30),
+            U256::exp10(18)
+        );
+        assert_eq!(
+            ContractOracle::to_usd_decimals(U256::MAX, MAX_DECIMALS),
+            U256::MAX / U256::exp10(59)
+        );
+        assert_eq!(ContractOracle::to_usd_decimals(U256::MAX, 0), U256::MAX);
+    }
+}
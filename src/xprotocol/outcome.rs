@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use web3::types::{Log, H160};
+
+use crate::{
+    datas::{data::AppData, BoxedSyncResult},
+    entity::transfers,
+};
+
+use super::abi::outcome_token::logs::{Burn, Transfer};
+
+pub struct OutcomeToken;
+
+impl OutcomeToken {
+    /*
+        选项代币的Transfer写入transfers流水,账户持仓由流水汇总
+    */
+    pub async fn with_transfer(
+        data: &Arc<AppData>,
+        log: &Log,
+        transfer: &Transfer,
+        proposal_id: u64,
+        token_index: usize,
+    ) -> BoxedSyncResult<()> {
+        let model = transfers::Model {
+            transaction_hash: format!("{:?}", log.transaction_hash.ok_or("txid is none")?),
+            log_index: log.log_index.ok_or("log index is none")?.as_u32(),
+            proposal_id,
+            token: format!("{:?}", log.address),
+            token_index: token_index as u32,
+            sender: format!("{:?}", transfer.from),
+            receiver: format!("{:?}", transfer.to),
+            amount: format!("{}", transfer.value),
+            block: log.block_number.ok_or("block number is none")?.as_u64(),
+        };
+        data.store
+            .write_transfer(model)
+            .await
+            .map_err(|e| e.to_string().into())
+    }
+
+    /*
+        abi中burn单独发出Burn事件,按转给零地址写入流水,持仓减少
+        同一交易中已有对应的Transfer(to=0)时不再写入,避免重复扣减
+    */
+    pub async fn with_burn(
+        data: &Arc<AppData>,
+        log: &Log,
+        burn: &Burn,
+        proposal_id: u64,
+        token_index: usize,
+    ) -> BoxedSyncResult<()> {
+        let transfer = Transfer {
+            from: burn.from,
+            to: H160::zero(),
+            value: burn.value,
+        };
+        Self::with_transfer(data, log, &transfer, proposal_id, token_index).await
+    }
+
+    //logs中是否有与burn对应的Transfer(to=0)
+    pub fn burn_transferred(logs: &[(&Log, Transfer)], log: &Log, burn: &Burn) -> bool {
+        logs.iter().any(|(other, transfer)| {
+            other.transaction_hash == log.transaction_hash
+                && other.address == log.address
+                && transfer.from == burn.from
+                && transfer.to.is_zero()
+                && transfer.value == burn.value
+        })
+    }
+}
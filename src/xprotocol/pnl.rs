@@ -0,0 +1,518 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use web3::types::{H160, U256};
+
+use crate::{
+    datas::{
+        data::AppData,
+        handle::{Amount, PnlItem, PRICE_DECIMALS},
+        BoxedSyncResult,
+    },
+    entity::{liquidity_events, lp_fees, pnl, pnl_realized, trades, transfers},
+};
+
+//feeRatio按万分比估算手续费
+const FEE_RATIO_BASE: u64 = 10000;
+
+pub struct Pnl;
+
+#[derive(Default, Clone, Copy)]
+struct Position {
+    quantity: U256,
+    cost: U256,
+    realized: i128,
+    bought: U256,
+    sold: U256,
+    block: u64,
+}
+
+impl Pnl {
+    /*
+        按交易流水重算提案的盈亏并整体替换pnl表,同时重算流动性提供者的手续费流水
+        成本按平均成本法,Buy/Sell的amount为抵押代币数量,
+        选项代币数量取同一交易内该账户对应选项代币的Transfer
+    */
+    pub async fn replay(data: &Arc<AppData>, proposal_ids: Vec<u64>) -> BoxedSyncResult<()> {
+        let store = &data.store;
+        for proposal_id in proposal_ids {
+            let trades = store
+                .read_proposal_trades(proposal_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let transfers = store
+                .read_proposal_transfers(proposal_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let events = store
+                .read_liquidity_events(proposal_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let fee_ratio = store
+                .read_proposal_fee_ratio(proposal_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let fee_ratio = U256::from_dec_str(&fee_ratio).unwrap_or_default();
+            let (positions, realized) = Self::replay_trades(&trades, &transfers, |token| {
+                data.get_outcome_token(token).map(|(_, index)| index)
+            });
+            let fees = Self::lp_fees(&trades, &events, fee_ratio);
+            let positions = positions
+                .into_iter()
+                .map(|((account, token_index), position)| pnl::Model {
+                    proposal_id,
+                    account,
+                    token_index: token_index as u32,
+                    quantity: position.quantity.to_string(),
+                    cost: position.cost.to_string(),
+                    realized: position.realized.to_string(),
+                    bought: position.bought.to_string(),
+                    sold: position.sold.to_string(),
+                    block: position.block,
+                })
+                .collect::<Vec<pnl::Model>>();
+            store
+                .write_pnl(proposal_id, positions, realized, fees)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    //全部提案从头重算
+    pub async fn replay_all(data: &Arc<AppData>) -> BoxedSyncResult<()> {
+        let proposal_ids = data
+            .get_proposals()
+            .iter()
+            .filter_map(|addr| data.get_proposal_id(addr))
+            .collect::<Vec<u64>>();
+        Self::replay(data, proposal_ids).await
+    }
+
+    //outcome_index为已登记选项代币的序号,未登记时取转账流水中的序号
+    fn replay_trades(
+        trades: &[trades::Model],
+        transfers: &[transfers::Model],
+        outcome_index: impl Fn(&H160) -> Option<usize>,
+    ) -> (HashMap<(String, usize), Position>, Vec<pnl_realized::Model>) {
+        let mut by_tx = HashMap::<&str, Vec<&transfers::Model>>::new();
+        let mut token_index = HashMap::<&str, usize>::new();
+        for transfer in transfers {
+            by_tx
+                .entry(transfer.transaction_hash.as_str())
+                .or_default()
+                .push(transfer);
+            token_index.insert(transfer.token.as_str(), transfer.token_index as usize);
+        }
+        let mut consumed = HashSet::<(&str, u32)>::new();
+        let mut positions = HashMap::<(String, usize), Position>::new();
+        let mut realized = Vec::<pnl_realized::Model>::new();
+        for trade in trades {
+            let index = trade
+                .token
+                .parse::<H160>()
+                .ok()
+                .and_then(|token| outcome_index(&token))
+                .or_else(|| token_index.get(trade.token.as_str()).copied())
+                .unwrap_or_default();
+            let amount = U256::from_dec_str(&trade.amount).unwrap_or_default();
+            let is_buy = trade.side == "Buy";
+            //同一交易内该账户收到(Buy)或转出(Sell)的选项代币
+            let mut quantity = U256::zero();
+            if let Some(list) = by_tx.get(trade.transaction_hash.as_str()) {
+                let matched = list
+                    .iter()
+                    .filter(|t| {
+                        t.token == trade.token
+                            && !consumed.contains(&(t.transaction_hash.as_str(), t.log_index))
+                            && if is_buy {
+                                t.receiver == trade.account
+                            } else {
+                                t.sender == trade.account
+                            }
+                    })
+                    .collect::<Vec<_>>();
+                let before = matched
+                    .iter()
+                    .filter(|t| t.log_index < trade.log_index)
+                    .copied()
+                    .collect::<Vec<_>>();
+                let used = if before.is_empty() {
+                    matched.into_iter().take(1).collect()
+                } else {
+                    before
+                };
+                for t in used {
+                    consumed.insert((t.transaction_hash.as_str(), t.log_index));
+                    quantity =
+                        quantity.saturating_add(U256::from_dec_str(&t.amount).unwrap_or_default());
+                }
+            }
+            //没有转账流水时按1:1估算
+            if quantity.is_zero() {
+                quantity = amount;
+            }
+            let position = positions.entry((trade.account.clone(), index)).or_default();
+            if is_buy {
+                Self::buy(position, amount, quantity);
+            } else {
+                //记录每笔卖出的已实现盈亏,用于按时间窗口统计
+                let before = position.realized;
+                Self::sell(position, amount, quantity);
+                realized.push(pnl_realized::Model {
+                    transaction_hash: trade.transaction_hash.clone(),
+                    log_index: trade.log_index,
+                    proposal_id: trade.proposal_id,
+                    account: trade.account.clone(),
+                    token_index: index as u32,
+                    realized: position.realized.saturating_sub(before).to_string(),
+                    ts: trade.ts,
+                });
+            }
+            position.block = trade.block;
+        }
+        (positions, realized)
+    }
+
+    /*
+        每笔交易的手续费按交易前各账户持有的LP份额分配
+        份额取账户在交易(block, log_index)之前最后一条流动性流水的shares,
+        之后加入的不分配,中途退出的只分配退出前的交易
+    */
+    fn lp_fees(
+        trades: &[trades::Model],
+        events: &[liquidity_events::Model],
+        fee_ratio: U256,
+    ) -> Vec<lp_fees::Model> {
+        let mut shares = BTreeMap::<&str, U256>::new();
+        let mut events = events.iter().peekable();
+        let mut fees = Vec::<lp_fees::Model>::new();
+        for trade in trades {
+            let position = (trade.block, trade.log_index);
+            while let Some(event) = events.next_if(|e| (e.block, e.log_index) < position) {
+                let balance = U256::from_dec_str(&event.shares).unwrap_or_default();
+                shares.insert(event.account.as_str(), balance);
+            }
+            let total = shares
+                .values()
+                .fold(U256::zero(), |total, share| total.saturating_add(*share));
+            if total.is_zero() {
+                continue;
+            }
+            let amount = U256::from_dec_str(&trade.amount).unwrap_or_default();
+            let fee = amount.saturating_mul(fee_ratio) / FEE_RATIO_BASE;
+            for (account, share) in shares.iter() {
+                let fee = fee.saturating_mul(*share) / total;
+                if fee.is_zero() {
+                    continue;
+                }
+                fees.push(lp_fees::Model {
+                    transaction_hash: trade.transaction_hash.clone(),
+                    log_index: trade.log_index,
+                    account: account.to_string(),
+                    proposal_id: trade.proposal_id,
+                    fee: fee.to_string(),
+                    ts: trade.ts,
+                });
+            }
+        }
+        fees
+    }
+
+    fn buy(position: &mut Position, amount: U256, quantity: U256) {
+        position.quantity = position.quantity.saturating_add(quantity);
+        position.cost = position.cost.saturating_add(amount);
+        position.bought = position.bought.saturating_add(amount);
+    }
+
+    fn sell(position: &mut Position, amount: U256, quantity: U256) {
+        //按卖出比例释放成本,超出持仓部分(外部转入)成本为0
+        let released = if position.quantity.is_zero() {
+            U256::zero()
+        } else {
+            position
+                .cost
+                .saturating_mul(quantity.min(position.quantity))
+                / position.quantity
+        };
+        position.realized = position
+            .realized
+            .saturating_add(Self::to_signed(amount))
+            .saturating_sub(Self::to_signed(released));
+        position.cost = position.cost.saturating_sub(released);
+        position.quantity = position.quantity.saturating_sub(quantity);
+        position.sold = position.sold.saturating_add(amount);
+    }
+
+    fn to_signed(value: U256) -> i128 {
+        if value > U256::from(i128::MAX as u128) {
+            i128::MAX
+        } else {
+            value.low_u128() as i128
+        }
+    }
+
+    /*
+        按最新价格估算未实现盈亏,提案结束后按结果计算最终兑付
+        price按PRICE_DECIMALS放大,result为胜出选项的序号,decimals为结算币种精度
+    */
+    pub fn valuation(
+        proposal_id: u64,
+        account: String,
+        decimals: u8,
+        rows: &[pnl::Model],
+        price: [U256; 2],
+        result: Option<u64>,
+    ) -> PnlItem {
+        let mut quantity = [U256::zero(); 2];
+        let mut cost = [U256::zero(); 2];
+        let (mut bought, mut sold, mut realized) = (U256::zero(), U256::zero(), 0i128);
+        for row in rows {
+            let index = row.token_index as usize % 2;
+            quantity[index] = U256::from_dec_str(&row.quantity).unwrap_or_default();
+            cost[index] = U256::from_dec_str(&row.cost).unwrap_or_default();
+            bought = bought.saturating_add(U256::from_dec_str(&row.bought).unwrap_or_default());
+            sold = sold.saturating_add(U256::from_dec_str(&row.sold).unwrap_or_default());
+            realized = realized.saturating_add(row.realized.parse::<i128>().unwrap_or_default());
+        }
+        let total_cost = Self::to_signed(cost[0].saturating_add(cost[1]));
+        let scale = U256::exp10(PRICE_DECIMALS);
+        let value = quantity[0].saturating_mul(price[0]) / scale
+            + quantity[1].saturating_mul(price[1]) / scale;
+        let unrealized = Self::to_signed(value).saturating_sub(total_cost);
+        //胜出选项按1:1兑付,其余归零
+        let payout = result.and_then(|result| quantity.get(result as usize).copied());
+        let total = match payout {
+            Some(payout) => realized
+                .saturating_add(Self::to_signed(payout))
+                .saturating_sub(total_cost),
+            None => realized.saturating_add(unrealized),
+        };
+        let amount = |raw: String| Amount::new(raw, decimals);
+        PnlItem {
+            proposal_id,
+            account,
+            decimals,
+            quantity: quantity.map(|q| amount(q.to_string())),
+            cost: cost.map(|c| amount(c.to_string())),
+            bought: amount(bought.to_string()),
+            sold: amount(sold.to_string()),
+            realized: amount(realized.to_string()),
+            unrealized: match payout {
+                Some(_) => amount("0".to_string()),
+                None => amount(unrealized.to_string()),
+            },
+            payout: payout.map(|payout| amount(payout.to_string())),
+            total: amount(total.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::{H160, U256};
+
+    use super::Pnl;
+    use crate::entity::{liquidity_events, pnl, trades, transfers};
+
+    const ACCOUNT: &str = "0x00000000000000000000000000000000000000c1";
+    const POOL: &str = "0x00000000000000000000000000000000000000b1";
+    const TOKEN: &str = "0x00000000000000000000000000000000000000a1";
+
+    fn trade(tx: &str, log_index: u32, side: &str, amount: u64) -> trades::Model {
+        trades::Model {
+            transaction_hash: tx.to_string(),
+            log_index,
+            proposal_id: 1,
+            block: log_index as u64,
+            account: ACCOUNT.to_string(),
+            token: TOKEN.to_string(),
+            side: side.to_string(),
+            amount: amount.to_string(),
+            ts: 0,
+        }
+    }
+
+    fn transfer(
+        tx: &str,
+        log_index: u32,
+        sender: &str,
+        receiver: &str,
+        amount: u64,
+    ) -> transfers::Model {
+        transfers::Model {
+            transaction_hash: tx.to_string(),
+            log_index,
+            proposal_id: 1,
+            token: TOKEN.to_string(),
+            token_index: 1,
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            amount: amount.to_string(),
+            block: log_index as u64,
+        }
+    }
+
+    fn registered(token: &H160) -> Option<usize> {
+        (*token == TOKEN.parse::<H160>().unwrap()).then_some(1)
+    }
+
+    #[test]
+    fn average_cost_releases_cost_by_sold_share() {
+        let trades = vec![
+            trade("0x01", 2, "Buy", 100),
+            trade("0x02", 2, "Buy", 300),
+            trade("0x03", 2, "Sell", 250),
+        ];
+        let transfers = vec![
+            transfer("0x01", 1, POOL, ACCOUNT, 200),
+            transfer("0x02", 1, POOL, ACCOUNT, 200),
+            transfer("0x03", 1, ACCOUNT, POOL, 150),
+        ];
+        let (positions, realized) = Pnl::replay_trades(&trades, &transfers, registered);
+        let position = positions[&(ACCOUNT.to_string(), 1)];
+        //持仓400成本400,卖出150释放成本150
+        assert_eq!(position.quantity, U256::from(250));
+        assert_eq!(position.cost, U256::from(250));
+        assert_eq!(position.realized, 100);
+        assert_eq!(position.bought, U256::from(400));
+        assert_eq!(position.sold, U256::from(250));
+        assert_eq!(realized.len(), 1);
+        assert_eq!(realized[0].realized, "100");
+    }
+
+    #[test]
+    fn quantity_falls_back_to_amount_and_transfer_index() {
+        //没有转账流水时按1:1估算,未登记的代币取流水中的序号
+        let trades = vec![trade("0x01", 2, "Buy", 100), trade("0x02", 2, "Sell", 40)];
+        let (positions, _) = Pnl::replay_trades(&trades, &[], |_| None);
+        let position = positions[&(ACCOUNT.to_string(), 0)];
+        assert_eq!(position.quantity, U256::from(60));
+        assert_eq!(position.cost, U256::from(60));
+        assert_eq!(position.realized, 0);
+
+        let transfers = vec![transfer("0x01", 1, POOL, ACCOUNT, 100)];
+        let (positions, _) = Pnl::replay_trades(&trades[..1], &transfers, |_| None);
+        assert!(positions.contains_key(&(ACCOUNT.to_string(), 1)));
+    }
+
+    #[test]
+    fn selling_transferred_in_tokens_has_no_cost() {
+        //外部转入的部分没有成本,卖出全部计入已实现盈亏
+        let trades = vec![trade("0x01", 2, "Buy", 50), trade("0x02", 2, "Sell", 120)];
+        let transfers = vec![
+            transfer("0x01", 1, POOL, ACCOUNT, 100),
+            transfer("0x02", 1, ACCOUNT, POOL, 200),
+        ];
+        let (positions, _) = Pnl::replay_trades(&trades, &transfers, registered);
+        let position = positions[&(ACCOUNT.to_string(), 1)];
+        assert_eq!(position.quantity, U256::zero());
+        assert_eq!(position.cost, U256::zero());
+        assert_eq!(position.realized, 70);
+    }
+
+    #[test]
+    fn transfers_are_matched_once_per_trade() {
+        //同一交易两笔买入各自对应之前的转账
+        let trades = vec![trade("0x01", 2, "Buy", 10), trade("0x01", 4, "Buy", 20)];
+        let transfers = vec![
+            transfer("0x01", 1, POOL, ACCOUNT, 15),
+            transfer("0x01", 3, POOL, ACCOUNT, 25),
+        ];
+        let (positions, _) = Pnl::replay_trades(&trades, &transfers, registered);
+        let position = positions[&(ACCOUNT.to_string(), 1)];
+        assert_eq!(position.quantity, U256::from(40));
+        assert_eq!(position.cost, U256::from(30));
+    }
+
+    fn row(token_index: u32, quantity: u64, cost: u64, realized: i64) -> pnl::Model {
+        pnl::Model {
+            proposal_id: 1,
+            account: ACCOUNT.to_string(),
+            token_index,
+            quantity: quantity.to_string(),
+            cost: cost.to_string(),
+            realized: realized.to_string(),
+            bought: cost.to_string(),
+            sold: "0".to_string(),
+            block: 1,
+        }
+    }
+
+    #[test]
+    fn valuation_uses_price_until_resolved() {
+        let rows = vec![row(0, 200, 100, 0), row(1, 100, 80, 10)];
+        //价格按18位精度放大,0.6和0.4
+        let price = [U256::exp10(17) * 6, U256::exp10(17) * 4];
+        let item = Pnl::valuation(1, ACCOUNT.to_string(), 0, &rows, price, None);
+        assert_eq!(item.unrealized.value, "-20");
+        assert_eq!(item.total.value, "-10");
+        assert!(item.payout.is_none());
+
+        //结算后胜出选项1:1兑付,未实现盈亏为0
+        let item = Pnl::valuation(1, ACCOUNT.to_string(), 0, &rows, price, Some(0));
+        assert_eq!(
+            item.payout.map(|payout| payout.value),
+            Some("200".to_string())
+        );
+        assert_eq!(item.unrealized.value, "0");
+        assert_eq!(item.total.value, "30");
+    }
+
+    fn liquidity(block: u64, account: &str, side: &str, shares: u64) -> liquidity_events::Model {
+        liquidity_events::Model {
+            transaction_hash: format!("0xl{}", block),
+            log_index: 1,
+            proposal_id: 1,
+            block,
+            account: account.to_string(),
+            side: side.to_string(),
+            amount: shares.to_string(),
+            shares: shares.to_string(),
+            ts: 0,
+        }
+    }
+
+    #[test]
+    fn lp_fees_follow_share_at_trade() {
+        let lp = "0x00000000000000000000000000000000000000d1";
+        let events = [
+            liquidity(1, ACCOUNT, "Add", 100),
+            liquidity(3, lp, "Add", 300),
+            liquidity(5, ACCOUNT, "Remove", 0),
+        ];
+        //区块3的交易在加入流动性的日志之前
+        let trades = [
+            trade("0x0", 0, "Buy", 10000),
+            trade("0x2", 2, "Buy", 10000),
+            trades::Model {
+                block: 3,
+                ..trade("0x3", 0, "Sell", 10000)
+            },
+            trade("0x4", 4, "Buy", 10000),
+            trade("0x6", 6, "Buy", 20000),
+        ];
+        let fees = Pnl::lp_fees(&trades, &events, U256::from(30))
+            .into_iter()
+            .map(|fee| (fee.transaction_hash, fee.account, fee.fee))
+            .collect::<Vec<_>>();
+        let fee = |tx: &str, account: &str, fee: &str| {
+            (tx.to_string(), account.to_string(), fee.to_string())
+        };
+        assert_eq!(
+            fees,
+            vec![
+                //没有流动性时不分配
+                fee("0x2", ACCOUNT, "30"),
+                fee("0x3", ACCOUNT, "30"),
+                //按交易前的份额1:3分配
+                fee("0x4", ACCOUNT, "7"),
+                fee("0x4", lp, "22"),
+                //退出后不再分配
+                fee("0x6", lp, "60"),
+            ]
+        );
+    }
+}
@@ -1,159 +1,154 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use web3::{
     contract::{Contract, Options},
-    ethabi::{self, Uint},
-    transports,
-    types::{BlockId, FilterBuilder, Log, H160, U256},
+    ethabi::Uint,
+    types::{BlockId, Bytes, CallRequest, Log, H160, U256},
     Web3,
 };
 
-use crate::datas::{data::AppData, handle::ProposalRelation, BoxedSyncResult};
+use crate::{
+    datas::{
+        data::AppData,
+        handle::{LiquiditySide, ProposalRelation, ProposalState, TradeSide},
+        BoxedSyncResult,
+    },
+    entity::{liquidity_events, trades},
+};
 
-use super::XProtocol;
+use super::{
+    abi::{
+        proposal::{self, logs::Initialize},
+        ProposalInfoView,
+    },
+    blocks::BlockTime,
+    events::Event,
+    transport::Failover,
+    volume::RollingVolume,
+    ProposalInfo,
+};
 
 pub struct Proposal;
 
 impl Proposal {
-    pub async fn update_24h_hot(
+    /*
+        写入交易流水,(交易hash, 日志序号)重复则忽略
+        新写入的交易累加到滚动窗口交易额
+    */
+    async fn write_trade(
         data: &Arc<AppData>,
-        web3: &Arc<Web3<transports::Http>>,
-        addrs: Vec<H160>,
-        hight: u64,
+        web3: &Arc<Web3<Failover>>,
+        log: &Log,
+        proposal_id: u64,
+        side: TradeSide,
+        (token, account, amount): (H160, H160, U256),
     ) -> BoxedSyncResult<()> {
-        if addrs.is_empty() {
-            return Ok(());
-        }
-        let store = &data.store;
-        //获取起点区块
-        let from_24h = if hight < (24 * 60 * 60 / 15) {
-            0
-        } else {
-            hight - (24 * 60 * 60 / 15)
+        let trade = trades::Model {
+            transaction_hash: format!("{:?}", log.transaction_hash.ok_or("txid is none")?),
+            log_index: log.log_index.ok_or("log index is none")?.as_u32(),
+            proposal_id,
+            block: log.block_number.ok_or("block number is none")?.as_u64(),
+            account: format!("{:?}", account),
+            token: format!("{:?}", token),
+            side: format!("{:?}", side),
+            amount: format!("{}", amount),
+            ts: Self::block_timestamp(data, web3, log).await?,
         };
-        //获取所有提案的24小时交易额
-        if let Ok(total) = Self::with_volume(web3, from_24h, hight, addrs.clone()).await {
-            for (addr, total) in total.iter() {
-                if let Some(proposal_id) = data.get_proposal_id(addr) {
-                    let volume = format!("{}", total);
-                    //写入交易额到数据库中
-                    if let Err(e) = store.write_volume24(proposal_id, volume).await {
-                        log::error!("{:?}", e);
-                    }
-                }
-            }
+        let inserted = data
+            .store
+            .write_trade(trade.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        if inserted {
+            RollingVolume::add(data, &trade).await?;
         }
         Ok(())
     }
 
-    /*
-        获取24h内的提案总交易额
-        根据add数组里的提案add,分别计算买卖总额,并返回所有提案add和对应的交易额
-    */
-    async fn with_volume(
-        web3: &Arc<Web3<transports::Http>>,
-        from_block: u64,
-        to_block: u64,
-        addrs: Vec<H160>,
-    ) -> BoxedSyncResult<HashMap<H160, u128>> {
-        let mut total = HashMap::<H160, u128>::new();
-        if addrs.is_empty() {
-            return Ok(total);
-        }
-        let filter = FilterBuilder::default()
-            .from_block(from_block.into())
-            .to_block(to_block.into())
-            .address(addrs)
-            .build();
-        let logs = web3.eth().logs(filter).await?;
-        let contract = ethabi::Contract::load(include_bytes!("../res/proposal_abi.json").as_ref())?;
-        for log in logs.iter() {
-            let raw_log = ethabi::RawLog {
-                topics: log.topics.clone(),
-                data: log.data.0.clone(),
-            };
-            let mut sum = 0;
-            for name in ["Buy", "Sell"] {
-                if let Ok(amount) = Self::parse_amount(&raw_log, &contract, name) {
-                    sum += amount;
-                }
+    //补取的历史日志中的Buy/Sell只写入交易流水,其他事件忽略
+    pub async fn backfill_trade(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        log: &Log,
+        event: &Event,
+        proposal_id: u64,
+    ) -> BoxedSyncResult<()> {
+        match event {
+            Event::Buy(buy) => {
+                let trade = (buy.token, buy.account, buy.amount);
+                Self::write_trade(data, web3, log, proposal_id, TradeSide::Buy, trade).await
             }
-
-            total
-                .entry(log.address)
-                .and_modify(|v| *v += sum)
-                .or_insert(sum);
+            Event::Sell(sell) => {
+                let trade = (sell.token, sell.account, sell.amount);
+                Self::write_trade(data, web3, log, proposal_id, TradeSide::Sell, trade).await
+            }
+            _ => Ok(()),
         }
-        Ok(total)
-    }
-    //解析日志里的买卖交易额，并返回
-    fn parse_amount(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<u128> {
-        // event Buy(uint tokenIndex, address account, uint256 amount);
-        // event Sell(uint tokenIndex, address account, uint256 amount);
-        let abi_log = XProtocol::parse_log(raw_log, contract, name)?;
-        XProtocol::get_index(&abi_log.params, 2)?
-            .value
-            .into_uint()
-            .map(|v| v.as_u128())
-            .ok_or_else(|| "convert to address error".into())
     }
 
-    fn parse_trade(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<H160> {
-        // event Buy(address token, address account, uint256 amount);
-        // event Sell(address token, address account, uint256 amount);
-
-        let abi_log = XProtocol::parse_log(raw_log, contract, name)?;
-        let params = abi_log.params;
-
-        let account = XProtocol::get_index(&params, 1)?
-            .value
-            .into_address()
-            .ok_or("convert to address error")?;
-
-        Ok(account)
+    //读取日志所在区块的时间戳
+    async fn block_timestamp(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        log: &Log,
+    ) -> BoxedSyncResult<u64> {
+        let number = log.block_number.ok_or("block number is none")?.as_u64();
+        BlockTime::timestamp(data, web3, number).await
     }
 
-    fn parse_liquidity(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<H160> {
-        // event AddLiquidity(address account, uint256 amount, uint256 proposalId);
-        // event RemoveLiquidity(address account, uint256 amount, uint256 proposalId);
-
-        let abi_log = XProtocol::parse_log(raw_log, contract, name)?;
-        let params = abi_log.params;
-
-        let account = XProtocol::get_index(&params, 0)?
-            .value
-            .into_address()
-            .ok_or("convert to address error")?;
-
-        Ok(account)
+    /*
+        写入流动性流水,shares为该区块账户持有的LP份额
+    */
+    async fn write_liquidity_event(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        log: &Log,
+        proposal_id: u64,
+        side: LiquiditySide,
+        (account, amount): (H160, U256),
+    ) -> BoxedSyncResult<()> {
+        let block_id = BlockId::Hash(log.block_hash.ok_or("block hash empty")?);
+        let proposal = Contract::from_json(
+            web3.eth(),
+            log.address,
+            include_bytes!("../res/proposal_abi.json"),
+        )?;
+        let shares: U256 = proposal
+            .query("balanceOf", (account,), None, Options::default(), block_id)
+            .await?;
+        let event = liquidity_events::Model {
+            transaction_hash: format!("{:?}", log.transaction_hash.ok_or("txid is none")?),
+            log_index: log.log_index.ok_or("log index is none")?.as_u32(),
+            proposal_id,
+            block: log.block_number.ok_or("block number is none")?.as_u64(),
+            account: format!("{:?}", account),
+            side: format!("{:?}", side),
+            amount: format!("{}", amount),
+            shares: format!("{}", shares),
+            ts: Self::block_timestamp(data, web3, log).await?,
+        };
+        data.store
+            .write_liquidity_event(event)
+            .await
+            .map_err(|e| e.to_string().into())
     }
 
+    /*
+        处理EventRegistry解码后的提案事件
+    */
     pub async fn with_proposal(
         data: &Arc<AppData>,
-        web3: &Arc<Web3<transports::Http>>,
+        web3: &Arc<Web3<Failover>>,
         log: &Log,
+        event: Event,
     ) -> BoxedSyncResult<()> {
         //获取proposalid
         let proposal_id = data
             .get_proposal_id(&log.address)
             .ok_or("proposal not exist")?;
-        //构建raw_log
-        let raw_log = ethabi::RawLog {
-            topics: log.topics.clone(),
-            data: log.data.0.clone(),
-        };
+        if let Event::Initialize(initialize) = &event {
+            return Self::with_initialize(data, log, initialize, proposal_id).await;
+        }
         //根据abi构建contract
         let proposal = Contract::from_json(
             web3.eth(),
@@ -161,75 +156,251 @@ impl Proposal {
             include_bytes!("../res/proposal_abi.json"),
         )?;
         let (volume_falg, liquidity_flag, price_flag) =
-            Self::update_volumeand_relation(data, &raw_log, proposal.abi(), proposal_id).await;
+            Self::update_volumeand_relation(data, web3, log, &event, proposal_id).await?;
+        let block_id = BlockId::Hash(log.block_hash.ok_or("block hash empty")?);
         // 交易额更新
         if volume_falg {
-            Self::update_history(data, web3, log, &proposal, proposal_id, 1).await?;
+            Self::update_history(data, web3, log.address, block_id, &proposal, proposal_id, 1)
+                .await?;
         }
         // 流动性更新
         if liquidity_flag {
-            Self::update_history(data, web3, log, &proposal, proposal_id, 2).await?;
+            Self::update_history(data, web3, log.address, block_id, &proposal, proposal_id, 2)
+                .await?;
         }
         // 价格更新
         if price_flag {
-            Self::update_history(data, web3, log, &proposal, proposal_id, 3).await?;
+            Self::update_history(data, web3, log.address, block_id, &proposal, proposal_id, 3)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /*
+        提案合约初始化时发出Initialize,记录创建者和初始流动性
+        事件中的提案地址和ID须与日志地址对应的提案一致
+    */
+    async fn with_initialize(
+        data: &Arc<AppData>,
+        log: &Log,
+        initialize: &Initialize,
+        proposal_id: u64,
+    ) -> BoxedSyncResult<()> {
+        //不一致的事件重新处理也不会成功,记录后忽略
+        if initialize.proposal != log.address || initialize.proposal_id != U256::from(proposal_id) {
+            log::warn!(
+                "initialize {} {:?} mismatch proposal {} {:?}",
+                initialize.proposal_id,
+                initialize.proposal,
+                proposal_id,
+                log.address
+            );
+            return Ok(());
+        }
+        let block = log.block_number.ok_or("block number is none")?.as_u64();
+        data.store
+            .write_proposal_creation(
+                proposal_id,
+                format!("{:?}", initialize.creater),
+                format!("{}", initialize.amount),
+                block,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    //读取提案合约proposalInfo
+    async fn call_proposal_info(
+        web3: &Arc<Web3<Failover>>,
+        address: H160,
+        block_id: Option<BlockId>,
+    ) -> BoxedSyncResult<ProposalInfoView> {
+        let call = CallRequest {
+            to: Some(address),
+            data: Some(Bytes(ProposalInfoView::encode_input())),
+            ..Default::default()
+        };
+        let output = web3.eth().call(call, block_id).await?;
+        ProposalInfoView::decode_output(&output.0)
+    }
+
+    //提案合约在block_id的状态,枚举顺序与ProposalState一致
+    pub async fn state_at(
+        web3: &Arc<Web3<Failover>>,
+        address: H160,
+        block_id: BlockId,
+    ) -> BoxedSyncResult<ProposalState> {
+        let input = proposal::functions::proposal_info_state::encode_input();
+        let call = CallRequest {
+            to: Some(address),
+            data: Some(Bytes(input)),
+            ..Default::default()
+        };
+        let output = web3.eth().call(call, Some(block_id)).await?;
+        let state = proposal::functions::proposal_info_state::decode_output(&output.0)?;
+        match u64::try_from(state) {
+            Ok(0) => Ok(ProposalState::Original),
+            Ok(1) => Ok(ProposalState::Formal),
+            Ok(2) => Ok(ProposalState::End),
+            _ => Err(format!("unknown proposal state {}", state).into()),
+        }
+    }
+
+    pub async fn proposal_info(
+        web3: &Arc<Web3<Failover>>,
+        address: H160,
+        block_id: Option<BlockId>,
+    ) -> BoxedSyncResult<ProposalInfo> {
+        let view = Self::call_proposal_info(web3, address, block_id).await?;
+        view.info()
+    }
+
+    /*
+        创建提案时的信息,在CreateProposal日志的区块读取,经多签或其他合约创建时同样有效
+        返回提案信息 结算币种 初始流动性 创建者
+    */
+    pub async fn creation_info(
+        web3: &Arc<Web3<Failover>>,
+        address: H160,
+        block_id: BlockId,
+    ) -> BoxedSyncResult<(ProposalInfo, H160, U256, H160)> {
+        let view = Self::call_proposal_info(web3, address, Some(block_id)).await?;
+        let proposal = Contract::from_json(
+            web3.eth(),
+            address,
+            include_bytes!("../res/proposal_abi.json"),
+        )?;
+        let creater: H160 = proposal
+            .query("creater", (), None, Options::default(), block_id)
+            .await?;
+        Ok((
+            view.info()?,
+            view.found_market,
+            view.initial_liquidity,
+            creater,
+        ))
+    }
+
+    /*
+        setProposalInfo没有事件,通过proposalInfo重新读取提案信息
+        单个提案失败时记录日志,继续刷新其他提案
+    */
+    pub async fn refresh_info(data: &Arc<AppData>, web3: &Arc<Web3<Failover>>, addrs: Vec<H160>) {
+        for addr in addrs {
+            if let Err(e) = Self::refresh_one(data, web3, addr).await {
+                log::error!("refresh proposal {:?} info error: {:?}", addr, e);
+            }
+        }
+    }
+
+    async fn refresh_one(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        addr: H160,
+    ) -> BoxedSyncResult<()> {
+        let proposal_id = data.get_proposal_id(&addr).ok_or("proposal not exist")?;
+        let info = Self::proposal_info(web3, addr, None).await?;
+        data.insert_outcome_tokens(proposal_id, info.tokens);
+        data.store
+            .write_proposal_info(proposal_id, &info)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /*
+        回滚后按指定区块重新读取提案的交易额和流动性
+    */
+    pub async fn refresh_totals(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        addrs: Vec<H160>,
+        block: u64,
+    ) -> BoxedSyncResult<()> {
+        let block_id = BlockId::Number(block.into());
+        for addr in addrs {
+            let proposal_id = data.get_proposal_id(&addr).ok_or("proposal not exist")?;
+            let proposal = Contract::from_json(
+                web3.eth(),
+                addr,
+                include_bytes!("../res/proposal_abi.json"),
+            )?;
+            for way in [1, 2] {
+                Self::update_history(data, web3, addr, block_id, &proposal, proposal_id, way)
+                    .await?;
+            }
         }
         Ok(())
     }
     //检索到事件 更新flag则为true
     async fn update_volumeand_relation(
         data: &Arc<AppData>,
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
+        web3: &Arc<Web3<Failover>>,
+        log: &Log,
+        event: &Event,
         proposal_id: u64,
-    ) -> (bool, bool, bool) {
+    ) -> BoxedSyncResult<(bool, bool, bool)> {
         let store = &data.store;
+        let block = log.block_number.ok_or("block number is none")?.as_u64();
         let mut volume_need_update = false;
         let mut liquidity_need_update = false;
         let mut price_need_update = false;
         //读取日志中的Buy Sell
         let relation = format!("{:?}", ProposalRelation::Trade);
-        for name in ["Buy", "Sell"] {
-            if let Ok(account) = Self::parse_trade(raw_log, contract, name) {
-                let account = format!("{:?}", account);
-                // 更新relation trade
-                if let Err(e) = store
-                    .write_relation(proposal_id, account, relation.clone())
-                    .await
-                {
-                    log::error!("{:?}", e);
-                }
-                volume_need_update = true;
-                price_need_update = true;
+        let trade = match event {
+            Event::Buy(buy) => Some((TradeSide::Buy, (buy.token, buy.account, buy.amount))),
+            Event::Sell(sell) => Some((TradeSide::Sell, (sell.token, sell.account, sell.amount))),
+            _ => None,
+        };
+        if let Some((side, trade)) = trade {
+            // 写入交易流水,失败时整个区间重新处理
+            Self::write_trade(data, web3, log, proposal_id, side, trade).await?;
+            let account = format!("{:?}", trade.1);
+            // 更新relation trade
+            if let Err(e) = store
+                .write_relation(proposal_id, account, relation, block)
+                .await
+            {
+                log::error!("{:?}", e);
             }
+            volume_need_update = true;
+            price_need_update = true;
         }
         let relation = format!("{:?}", ProposalRelation::Liquidity);
-        for name in ["AddLiquidity", "RemoveLiquidity"] {
-            if let Ok(account) = Self::parse_liquidity(raw_log, contract, name) {
-                let account = format!("{:?}", account);
-                if let Err(e) = store
-                    .write_relation(proposal_id, account, relation.clone())
-                    .await
-                {
-                    log::error!("{:?}", e);
-                }
-                liquidity_need_update = true;
-                price_need_update = true;
+        let liquidity = match event {
+            Event::AddLiquidity(add) => Some((LiquiditySide::Add, (add.account, add.amount))),
+            Event::RemoveLiquidity(remove) => {
+                Some((LiquiditySide::Remove, (remove.account, remove.amount)))
+            }
+            _ => None,
+        };
+        if let Some((side, liquidity)) = liquidity {
+            // 写入流动性流水,失败时整个区间重新处理
+            Self::write_liquidity_event(data, web3, log, proposal_id, side, liquidity).await?;
+            let account = format!("{:?}", liquidity.0);
+            if let Err(e) = store
+                .write_relation(proposal_id, account, relation, block)
+                .await
+            {
+                log::error!("{:?}", e);
             }
+            liquidity_need_update = true;
+            price_need_update = true;
         }
-        (volume_need_update, liquidity_need_update, price_need_update)
+        Ok((volume_need_update, liquidity_need_update, price_need_update))
     }
 
     async fn update_history(
         data: &Arc<AppData>,
-        web3: &Arc<Web3<transports::Http>>,
-        log: &Log,
-        proposal: &Contract<transports::Http>,
+        web3: &Arc<Web3<Failover>>,
+        address: H160,
+        block_id: BlockId,
+        proposal: &Contract<Failover>,
         proposal_id: u64,
         way: u32,
     ) -> BoxedSyncResult<()> {
         let store = &data.store;
-        let block_id = BlockId::Hash(log.block_hash.ok_or("block hash empty")?);
 
         match way {
             1 => {
@@ -255,7 +426,8 @@ impl Proposal {
             3 => {
                 let block = web3.eth().block(block_id).await?.ok_or("empty block")?;
                 let ts = block.timestamp.as_u64();
-                let mut tokens = [0u128; 2];
+                let number = block.number.ok_or("block number is none")?.as_u64();
+                let mut balances = [U256::zero(); 2];
                 for (i, &token_name) in ["token0", "token1"].iter().enumerate() {
                     let token = proposal
                         .query(token_name, (), None, Options::default(), block_id)
@@ -268,18 +440,16 @@ impl Proposal {
                     let total: U256 = contract
                         .query(
                             "balanceOf",
-                            (log.address,),
+                            (address,),
                             None,
                             Options::default(),
                             block_id,
                         )
                         .await?;
-                    tokens[i] = total.as_u128();
+                    balances[i] = total;
                 }
-                tokens[0] = tokens[0] * 100 / (tokens[0] + tokens[1]);
-                tokens[1] = 100 - tokens[0];
                 store
-                    .write_price(proposal_id, ts, tokens)
+                    .write_price(proposal_id, ts, balances, number)
                     .await
                     .map_err(|e| e.to_string().into())
             }
@@ -1,9 +1,9 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use futures::{future::LocalBoxFuture, FutureExt};
 use web3::{
-    ethabi, transports,
-    types::{Log, TransactionId, H160},
+    contract::{Contract, Options},
+    types::{BlockId, Log, TransactionId, H160, U256},
     Web3,
 };
 
@@ -16,150 +16,217 @@ use crate::{
     },
 };
 
-use super::{BoxFn, ModuleTest, ProposalStatus, XProtocol};
+use super::{
+    abi::UploadResultCall,
+    events::{Event, EventSource},
+    proposals::Proposal,
+    transport::Failover,
+    ModuleTest, ProposalResolution, ProposalStatus,
+};
 
 pub struct Router {
     data: Arc<AppData>,
-    web3: Arc<Web3<transports::Http>>,
+    web3: Arc<Web3<Failover>>,
+    //是否解析交易输入作为补充
+    calldata_fallback: bool,
 }
 
 impl Router {
-    pub fn new(data: Arc<AppData>, web3: Arc<Web3<transports::Http>>) -> Self {
-        Self { data, web3 }
-    }
-
-    fn parse_proposal_id(
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        name: &str,
-    ) -> BoxedSyncResult<u64> {
-        // event AcceptStateFormalPrediction(uint proposalId);
-        // event AcceptProposalEnd (uint proposalId);
-        let abi_log = XProtocol::parse_log(raw_log, contract, name)?;
-        let params = abi_log.params;
-
-        XProtocol::get_index(&params, 0)?
-            .value
-            .into_uint()
-            .map(|v| v.as_u64())
-            .ok_or_else(|| "convert to uint error".into())
-    }
-
-    fn get_state_test_fn() -> HashMap<ProposalState, BoxFn<u64>> {
-        let mut box_fns = HashMap::<ProposalState, BoxFn<u64>>::new();
-        box_fns.insert(
-            ProposalState::Formal,
-            Box::new(|raw_log, contract| {
-                Self::parse_proposal_id(raw_log, contract, "AcceptStateFormalPrediction")
-            }),
-        );
-        box_fns.insert(
-            ProposalState::End,
-            Box::new(|raw_log, contract| {
-                Self::parse_proposal_id(raw_log, contract, "AcceptProposalEnd")
-            }),
-        );
-        box_fns
-    }
-
-    async fn with_router(&self, log: &Log) -> BoxedSyncResult<()> {
-        //构建raw_log
-        let raw_log = ethabi::RawLog {
-            topics: log.topics.clone(),
-            data: log.data.0.clone(),
-        };
-        //根据abi构建contract
-        let contract = ethabi::Contract::load(include_bytes!("../res/router_abi.json").as_ref())?;
-        //根据交易hash更新提案状态
-        self.update_proposal_state(
-            &raw_log,
-            &contract,
-            TransactionId::Hash(log.transaction_hash.ok_or("txid is none")?),
-        )
-        .await
+    pub fn new(data: Arc<AppData>, web3: Arc<Web3<Failover>>, calldata_fallback: bool) -> Self {
+        Self {
+            data,
+            web3,
+            calldata_fallback,
+        }
     }
 
-    async fn update_proposal_state(
-        &self,
-        raw_log: &ethabi::RawLog,
-        contract: &ethabi::Contract,
-        transaction_id: TransactionId,
-    ) -> BoxedSyncResult<()> {
+    /*
+        根据事件更新提案状态,不解析交易输入,经多签或其他合约调用路由时同样有效
+        审核状态按提案合约在事件前一个区块的proposalInfoState判断:
+        Original直接结束为审核不通过,进入Formal为审核通过
+    */
+    async fn update_proposal_state(&self, log: &Log, event: Event) -> BoxedSyncResult<()> {
         // 数据库写入
         let store = &self.data.store;
-        // 读取交易hash中的审核操作
-        let transaction = self
-            .web3
-            .eth()
-            .transaction(transaction_id)
-            .await?
-            .ok_or("get transaction return nill")?;
-        if let Ok((proposal_address, audit_state)) = Self::parse_factory_function(
-            contract,
-            "acceptStateFormalPrediction",
-            &transaction.input.0[4..],
-        ) {
-            match audit_state {
-                false => {
-                    //不通过 End 地址
-                    let account = format!("{:?}", proposal_address);
-                    let audit_state = format!("{:?}", ProposalAduitState::NotPassed);
-                    if let Err(e) = store.write_proposal_audit_state(account, audit_state).await {
-                        log::error!("write proposal state error: {:?}", e);
-                    }
-                }
-                true => {
-                    //通过 formal 地址
-                    let account = format!("{:?}", proposal_address);
-                    let audit_state = format!("{:?}", ProposalAduitState::Passed);
-                    if let Err(e) = store.write_proposal_audit_state(account, audit_state).await {
-                        log::error!("write proposal state error: {:?}", e);
+        //读取event 修改数据库的提案状态
+        let (state, proposal_id) = match event {
+            Event::AcceptStateFormalPrediction(event) => (ProposalState::Formal, event.proposal_id),
+            Event::AcceptProposalEnd(event) => (ProposalState::End, event.proposal_id),
+            _ => return Ok(()),
+        };
+        let proposal_id = u64::try_from(proposal_id)
+            .map_err(|_| format!("proposal id {} overflow", proposal_id))?;
+        let block = log.block_number.ok_or("block number is none")?.as_u64();
+        if let Some(address) = self.data.get_proposal_address(proposal_id) {
+            let parent = BlockId::Number(block.saturating_sub(1).into());
+            match Proposal::state_at(&self.web3, address, parent).await {
+                Ok(previous) => {
+                    if let Some(audit_state) = Self::audit_state(previous, state) {
+                        let account = format!("{:?}", address);
+                        let audit_state = format!("{:?}", audit_state);
+                        if let Err(e) = store.write_proposal_audit_state(account, audit_state).await
+                        {
+                            log::error!("write proposal state error: {:?}", e);
+                        }
                     }
                 }
+                Err(e) => log::error!("read proposal {} state error: {:?}", proposal_id, e),
             }
         }
-        //读取event 修改数据库的提案状态
-        let box_fns = Self::get_state_test_fn();
-        for (&state, test_fn) in box_fns.iter() {
-            if let Ok(proposal_id) = test_fn(&raw_log, &contract) {
-                self.data.set_proposal_state(proposal_id, state);
-                if let Some(address) = self.data.get_proposal_address(proposal_id) {
-                    self.data.push_to_client(
-                        "proposalStatus",
-                        SubOpCode::Update,
-                        ProposalStatus::new(proposal_id, address, state),
-                    );
+        self.data.set_proposal_state(proposal_id, state);
+        //提案结束 记录结果和结束时的余额
+        let resolution = if state == ProposalState::End {
+            match self.update_resolution(log, proposal_id).await {
+                Ok(resolution) => Some(resolution),
+                Err(e) => {
+                    log::error!("update resolution error: {:?}", e);
+                    None
                 }
-                let proposal_id = format!("{:?}", proposal_id);
-                let state = format!("{:?}", state);
-                if let Err(e) = store.write_proposal_state(proposal_id, state).await {
-                    log::error!("write proposal state error: {:?}", e);
-                }
-                break;
             }
+        } else {
+            None
+        };
+        if let Some(address) = self.data.get_proposal_address(proposal_id) {
+            self.data.push_to_client(
+                "proposalStatus",
+                SubOpCode::Update,
+                ProposalStatus::new(proposal_id, address, state).with_resolution(resolution),
+            );
+        }
+        //进入Formal后提案信息不再修改,刷新一次
+        if state == ProposalState::Formal {
+            if let Some(address) = self.data.get_proposal_address(proposal_id) {
+                Proposal::refresh_info(&self.data, &self.web3, vec![address]).await;
+            }
+        }
+        let proposal_id = format!("{:?}", proposal_id);
+        let state = format!("{:?}", state);
+        if let Err(e) = store.write_proposal_state(proposal_id, state, block).await {
+            log::error!("write proposal state error: {:?}", e);
         }
         Ok(())
     }
 
-    fn parse_factory_function(
-        contract: &ethabi::Contract,
-        name: &str,
-        input: &[u8],
-    ) -> BoxedSyncResult<(H160, bool)> {
-        let function = contract.function(name)?;
-        let params = function.decode_input(input)?;
-        let proposal_address = XProtocol::get_index(&params, 0)?
-            .into_address()
-            .ok_or("into address error")?;
-        let params2 = XProtocol::get_index(&params, 1)?
-            .into_bool()
-            .ok_or("into uint error")?;
-        Ok((proposal_address, params2))
+    //previous为事件前合约中的状态
+    fn audit_state(previous: ProposalState, state: ProposalState) -> Option<ProposalAduitState> {
+        match (previous, state) {
+            (ProposalState::Original, ProposalState::End) => Some(ProposalAduitState::NotPassed),
+            (_, ProposalState::Formal) => Some(ProposalAduitState::Passed),
+            _ => None,
+        }
+    }
+
+    /*
+        读取提案合约在结束区块的结果和余额,写入resolutions
+    */
+    async fn update_resolution(
+        &self,
+        log: &Log,
+        proposal_id: u64,
+    ) -> BoxedSyncResult<ProposalResolution> {
+        let address = self
+            .data
+            .get_proposal_address(proposal_id)
+            .ok_or("proposal not exist")?;
+        let block_id = BlockId::Hash(log.block_hash.ok_or("block hash empty")?);
+        let proposal = Contract::from_json(
+            self.web3.eth(),
+            address,
+            include_bytes!("../res/proposal_abi.json"),
+        )?;
+        let mut values = [U256::zero(); 4];
+        for (i, name) in [
+            "proposalResult",
+            "endBalanceToken0",
+            "endBalanceToken1",
+            "endTotalSupply",
+        ]
+        .iter()
+        .enumerate()
+        {
+            values[i] = proposal
+                .query(name, (), None, Options::default(), block_id)
+                .await?;
+        }
+        //开启calldata_fallback时与交易上传的结果核对
+        let upload_result = match self.calldata_fallback {
+            true => self.upload_result(log).await,
+            false => None,
+        };
+        if let Some((proposal_address, result)) = upload_result {
+            if proposal_address == address && result != values[0] {
+                log::warn!(
+                    "proposal {} upload result {} but proposalResult {}",
+                    proposal_id,
+                    result,
+                    values[0]
+                );
+            }
+        }
+        let block = self
+            .web3
+            .eth()
+            .block(block_id)
+            .await?
+            .ok_or("empty block")?;
+        let resolution = ProposalResolution {
+            result: u64::try_from(values[0])
+                .map_err(|_| format!("proposal result {} overflow", values[0]))?,
+            end_balance_token0: format!("{}", values[1]),
+            end_balance_token1: format!("{}", values[2]),
+            end_total_supply: format!("{}", values[3]),
+            block: log.block_number.ok_or("block number is none")?.as_u64(),
+            ts: block.timestamp.as_u64(),
+        };
+        self.data
+            .store
+            .write_resolution(proposal_id, format!("{:?}", address), &resolution)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(resolution)
+    }
+
+    /*
+        解析交易输入中的acceptProposalUploadResult,只适用于直接调用路由合约的交易
+    */
+    async fn upload_result(&self, log: &Log) -> Option<(H160, U256)> {
+        let transaction_id = TransactionId::Hash(log.transaction_hash?);
+        let transaction = self.web3.eth().transaction(transaction_id).await.ok()??;
+        let call = UploadResultCall::decode(&transaction.input.0).ok()?;
+        Some((call.proposal, call.result))
     }
 }
 
 impl ModuleTest for Router {
-    fn with_fn<'a>(&'a self, log: &'a Log) -> LocalBoxFuture<'a, BoxedSyncResult<()>> {
-        self.with_router(log).boxed()
+    fn source(&self) -> EventSource {
+        EventSource::Router
+    }
+
+    fn with_fn<'a>(
+        &'a self,
+        log: &'a Log,
+        event: Event,
+    ) -> LocalBoxFuture<'a, BoxedSyncResult<()>> {
+        self.update_proposal_state(log, event).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Router;
+    use crate::datas::handle::{ProposalAduitState, ProposalState};
+
+    #[test]
+    fn audit_state_from_previous_state() {
+        use ProposalState::*;
+        assert_eq!(
+            Router::audit_state(Original, End),
+            Some(ProposalAduitState::NotPassed)
+        );
+        assert_eq!(
+            Router::audit_state(Original, Formal),
+            Some(ProposalAduitState::Passed)
+        );
+        assert_eq!(Router::audit_state(Formal, End), None);
     }
 }
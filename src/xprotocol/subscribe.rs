@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use actix_web::rt::time as RuntimeTime;
+use futures::{channel::mpsc::UnboundedSender, stream::select_all, StreamExt};
+use web3::{
+    transports,
+    types::{FilterBuilder, H160, H256},
+    Web3,
+};
+
+use crate::datas::BoxedSyncResult;
+
+// 断线后重连的间隔(秒)
+const RECONNECT_INTERVAL: u64 = 5;
+
+/*
+    通过websocket订阅新区块,工厂和路由的日志以及提案事件,收到通知后唤醒BlockActor检索
+    提案事件按签名订阅,新提案不需要重新订阅
+    数据仍由BlockActor按http检索入库,断线期间退回http轮询
+    BlockActor从数据库记录的区块继续检索,重连前后的区块不会遗漏
+*/
+pub struct Subscriber {
+    url: String,
+    contracts: Vec<H160>,
+    topics: Vec<H256>,
+    wake: UnboundedSender<()>,
+}
+
+impl Subscriber {
+    pub fn new(
+        url: String,
+        contracts: Vec<H160>,
+        topics: Vec<H256>,
+        wake: UnboundedSender<()>,
+    ) -> Self {
+        Self {
+            url,
+            contracts,
+            topics,
+            wake,
+        }
+    }
+
+    //断线后重连,BlockActor停止后退出
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.subscribe().await {
+                log::error!("websocket subscribe error: {:?}", e);
+            }
+            if self.wake.is_closed() {
+                return;
+            }
+            log::warn!("websocket disconnected, fall back to http polling");
+            RuntimeTime::sleep(Duration::from_secs(RECONNECT_INTERVAL)).await;
+        }
+    }
+
+    async fn subscribe(&self) -> BoxedSyncResult<()> {
+        let web3 = Web3::new(transports::WebSocket::new(&self.url).await?);
+        let subscribe = web3.eth_subscribe();
+        let heads = subscribe.subscribe_new_heads().await?;
+        let contracts = FilterBuilder::default()
+            .address(self.contracts.clone())
+            .build();
+        let contracts = subscribe.subscribe_logs(contracts).await?;
+        let topics = FilterBuilder::default()
+            .topics(Some(self.topics.clone()), None, None, None)
+            .build();
+        let topics = subscribe.subscribe_logs(topics).await?;
+        log::info!("websocket subscribed {}", self.url);
+        //只用于唤醒,不关心通知内容
+        let mut notifications = select_all([
+            heads.map(|head| head.map(|_| ())).boxed(),
+            contracts.map(|log| log.map(|_| ())).boxed(),
+            topics.map(|log| log.map(|_| ())).boxed(),
+        ]);
+        while let Some(notification) = notifications.next().await {
+            notification?;
+            self.wake.unbounded_send(())?;
+        }
+        Err("subscription closed".into())
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Instant,
+};
+
+use futures::future::{join_all, BoxFuture};
+use jsonrpc_core::{Call, MethodCall, Value};
+use serde::Serialize;
+use web3::{error::Error, helpers, transports, types::U64, RequestId, Transport};
+
+use crate::datas::BoxedResult;
+
+// 延迟和错误率的平滑系数
+const SMOOTHING: f64 = 0.2;
+// 错误率折算为延迟的权重(毫秒)
+const ERROR_PENALTY: f64 = 10_000.0;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStatus {
+    pub url: String,
+    pub head: u64,
+    pub lag: u64,
+    pub latency_ms: u64,
+    pub error_rate: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub available: bool,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    head: u64,
+    latency: f64,
+    error_rate: f64,
+    requests: u64,
+    errors: u64,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    http: transports::Http,
+    health: RwLock<Health>,
+}
+
+impl Endpoint {
+    //记录一次请求的耗时和结果
+    fn record(&self, start: Instant, ok: bool) {
+        if let Ok(mut health) = self.health.write() {
+            let latency = start.elapsed().as_millis() as f64;
+            let failed = if ok { 0.0 } else { 1.0 };
+            if health.requests == 0 {
+                health.latency = latency;
+                health.error_rate = failed;
+            } else {
+                health.latency += (latency - health.latency) * SMOOTHING;
+                health.error_rate += (failed - health.error_rate) * SMOOTHING;
+            }
+            health.requests += 1;
+            if !ok {
+                health.errors += 1;
+            }
+        }
+    }
+
+    fn set_head(&self, head: u64) {
+        if let Ok(mut health) = self.health.write() {
+            health.head = health.head.max(head);
+        }
+    }
+}
+
+/*
+    多个rpc节点的故障转移传输
+    请求按延迟和错误率优先发往最健康的节点,节点不可达时依次换下一个
+    落后最高区块超过max_lag的节点不参与请求
+*/
+#[derive(Debug, Clone)]
+pub struct Failover {
+    endpoints: Arc<Vec<Endpoint>>,
+    max_lag: u64,
+    id: Arc<AtomicUsize>,
+    //固定发往的节点
+    pin: Option<usize>,
+}
+
+impl Failover {
+    //urls以逗号分隔
+    pub fn new(urls: &str, max_lag: u64) -> BoxedResult<Self> {
+        let endpoints = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                Ok(Endpoint {
+                    url: url.to_string(),
+                    http: transports::Http::new(url)?,
+                    health: RwLock::new(Health::default()),
+                })
+            })
+            .collect::<Result<Vec<Endpoint>, Error>>()?;
+        if endpoints.is_empty() {
+            return Err("no rpc endpoint".into());
+        }
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            max_lag,
+            id: Arc::new(AtomicUsize::new(0)),
+            pin: None,
+        })
+    }
+
+    /*
+        固定到当前最健康的节点,一个检索区间的区块和日志请求都发往该节点
+        固定的节点失败时不换节点,由调用方下一次重新检索该区间
+    */
+    pub fn pinned(&self) -> Self {
+        Self {
+            pin: self.ordered().first().copied(),
+            ..self.clone()
+        }
+    }
+
+    //所有节点的最新区块
+    fn max_head(&self) -> u64 {
+        self.endpoints
+            .iter()
+            .filter_map(|endpoint| endpoint.health.read().ok().map(|health| health.head))
+            .max()
+            .unwrap_or_default()
+    }
+
+    //可用节点按健康程度排序
+    fn ordered(&self) -> Vec<usize> {
+        let max_head = self.max_head();
+        let mut scores = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, endpoint)| {
+                let health = endpoint.health.read().ok()?;
+                if health.head + self.max_lag < max_head {
+                    return None;
+                }
+                Some((index, health.latency + health.error_rate * ERROR_PENALTY))
+            })
+            .collect::<Vec<(usize, f64)>>();
+        scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+        scores.into_iter().map(|(index, _)| index).collect()
+    }
+
+    //节点自身的故障,换下一个节点重试;合约执行失败等rpc错误直接返回
+    fn is_node_error(e: &Error) -> bool {
+        matches!(
+            e,
+            Error::Unreachable | Error::Transport(_) | Error::InvalidResponse(_) | Error::Io(_)
+        )
+    }
+
+    //向所有节点查询最新区块,更新落后情况
+    pub async fn check(&self) {
+        join_all(self.endpoints.iter().map(|endpoint| async move {
+            let start = Instant::now();
+            let head = endpoint
+                .http
+                .execute("eth_blockNumber", vec![])
+                .await
+                .and_then(|v| {
+                    serde_json::from_value::<U64>(v).map_err(|e| Error::Decoder(e.to_string()))
+                });
+            match head {
+                Ok(head) => {
+                    endpoint.record(start, true);
+                    endpoint.set_head(head.as_u64());
+                }
+                Err(e) => {
+                    endpoint.record(start, false);
+                    log::warn!("rpc {} health check error: {:?}", endpoint.url, e);
+                }
+            }
+        }))
+        .await;
+    }
+
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        let max_head = self.max_head();
+        self.endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let health = endpoint.health.read().ok()?;
+                Some(EndpointStatus {
+                    url: endpoint.url.clone(),
+                    head: health.head,
+                    lag: max_head.saturating_sub(health.head),
+                    latency_ms: health.latency as u64,
+                    error_rate: health.error_rate,
+                    requests: health.requests,
+                    errors: health.errors,
+                    available: health.head + self.max_lag >= max_head,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Transport for Failover {
+    type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let endpoints = self.endpoints.clone();
+        let order = match self.pin {
+            Some(index) => vec![index],
+            None => self.ordered(),
+        };
+        let block_number = matches!(
+            &request,
+            Call::MethodCall(MethodCall { method, .. }) if method == "eth_blockNumber"
+        );
+        Box::pin(async move {
+            let mut last = Error::Unreachable;
+            for index in order {
+                let endpoint = &endpoints[index];
+                let start = Instant::now();
+                match endpoint.http.send(id, request.clone()).await {
+                    Err(e) if Self::is_node_error(&e) => {
+                        endpoint.record(start, false);
+                        log::warn!("rpc {} error: {:?}", endpoint.url, e);
+                        last = e;
+                    }
+                    res => {
+                        endpoint.record(start, true);
+                        //顺带更新该节点的最新区块
+                        if let (true, Ok(v)) = (block_number, &res) {
+                            if let Ok(head) = serde_json::from_value::<U64>(v.clone()) {
+                                endpoint.set_head(head.as_u64());
+                            }
+                        }
+                        return res;
+                    }
+                }
+            }
+            Err(last)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Failover;
+
+    fn failover(health: &[(u64, f64, f64)]) -> Failover {
+        let urls = (0..health.len())
+            .map(|i| format!("http://127.0.0.1:{}", 8545 + i))
+            .collect::<Vec<String>>()
+            .join(",");
+        let failover = Failover::new(&urls, 5).unwrap();
+        for (endpoint, (head, latency, error_rate)) in failover.endpoints.iter().zip(health) {
+            let mut health = endpoint.health.write().unwrap();
+            health.head = *head;
+            health.latency = *latency;
+            health.error_rate = *error_rate;
+        }
+        failover
+    }
+
+    #[test]
+    fn ordered_by_latency_and_error_rate() {
+        //错误率按ERROR_PENALTY折算为延迟
+        let failover = failover(&[(100, 300.0, 0.0), (100, 50.0, 0.1), (100, 100.0, 0.0)]);
+        assert_eq!(failover.ordered(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn lagging_endpoints_excluded() {
+        let failover = failover(&[(100, 10.0, 0.0), (95, 20.0, 0.0), (94, 1.0, 0.0)]);
+        assert_eq!(failover.ordered(), vec![0, 1]);
+        let status = failover.status();
+        assert_eq!(status[2].lag, 6);
+        assert!(!status[2].available);
+    }
+
+    #[test]
+    fn pinned_to_healthiest_endpoint() {
+        let failover = failover(&[(100, 30.0, 0.0), (100, 10.0, 0.0)]);
+        assert_eq!(failover.pin, None);
+        assert_eq!(failover.pinned().pin, Some(1));
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use web3::Web3;
+
+use crate::{
+    datas::{data::AppData, BoxedSyncResult},
+    entity::trades,
+};
+
+use super::{blocks::BlockTime, transport::Failover};
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum VolumeWindow {
+    Day,
+    TwoDays,
+    Week,
+}
+
+impl VolumeWindow {
+    pub const WINDOWS: [Self; 3] = [Self::Day, Self::TwoDays, Self::Week];
+
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Self::Day => 24 * 60 * 60,
+            Self::TwoDays => 48 * 60 * 60,
+            Self::Week => 7 * 24 * 60 * 60,
+        }
+    }
+
+    //proposals表中对应的交易额字段
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Day => "volume24",
+            Self::TwoDays => "volume48",
+            Self::Week => "volume7d",
+        }
+    }
+}
+
+/*
+    按交易流水增量维护滚动窗口的交易额
+    窗口以已检索区块的时间为终点,起点区块按区块时间戳查找
+    新交易入库时累加,窗口前移时减去移出窗口的交易
+*/
+pub struct RollingVolume;
+
+impl RollingVolume {
+    //启动或回滚后按流水整体重算
+    pub async fn rebuild(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        hight: u64,
+    ) -> BoxedSyncResult<()> {
+        for window in VolumeWindow::WINDOWS {
+            let start = BlockTime::window_start(data, web3, hight, window.seconds()).await?;
+            data.store
+                .rebuild_window_volume(window.column(), start, hight)
+                .await
+                .map_err(|e| e.to_string())?;
+            data.set_window_start(window, start)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    //新写入的交易累加到包含它的窗口,窗口未初始化时等待重算
+    pub async fn add(data: &Arc<AppData>, trade: &trades::Model) -> BoxedSyncResult<()> {
+        let columns = Self::columns(trade.block, |window| data.get_window_start(window));
+        if columns.is_empty() {
+            return Ok(());
+        }
+        data.store
+            .add_window_volume(trade.proposal_id, trade.amount.clone(), columns)
+            .await
+            .map_err(|e| e.to_string().into())
+    }
+
+    //block所在窗口的交易额字段,start为窗口的起点区块
+    fn columns(block: u64, start: impl Fn(VolumeWindow) -> Option<u64>) -> Vec<&'static str> {
+        VolumeWindow::WINDOWS
+            .iter()
+            .filter(|window| start(**window).map(|start| block >= start).unwrap_or(false))
+            .map(|window| window.column())
+            .collect()
+    }
+
+    //窗口终点前移到hight,减去起点之前的交易,返回是否有窗口移动
+    pub async fn advance(
+        data: &Arc<AppData>,
+        web3: &Arc<Web3<Failover>>,
+        hight: u64,
+    ) -> BoxedSyncResult<bool> {
+        let mut moved = false;
+        for window in VolumeWindow::WINDOWS {
+            let old = match data.get_window_start(window) {
+                Some(old) => old,
+                None => continue,
+            };
+            let start = BlockTime::window_start(data, web3, hight, window.seconds()).await?;
+            if start <= old {
+                continue;
+            }
+            data.store
+                .age_window_volume(window.column(), old, start)
+                .await
+                .map_err(|e| e.to_string())?;
+            data.set_window_start(window, start)
+                .map_err(|e| e.to_string())?;
+            moved = true;
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RollingVolume, VolumeWindow};
+
+    #[test]
+    fn windows_grow_in_order() {
+        let seconds = VolumeWindow::WINDOWS.map(|window| window.seconds());
+        assert_eq!(seconds, [86400, 2 * 86400, 7 * 86400]);
+        let columns = VolumeWindow::WINDOWS.map(|window| window.column());
+        assert_eq!(columns, ["volume24", "volume48", "volume7d"]);
+    }
+
+    #[test]
+    fn trade_added_to_windows_containing_it() {
+        let start = |window| match window {
+            VolumeWindow::Day => Some(300),
+            VolumeWindow::TwoDays => Some(200),
+            VolumeWindow::Week => None,
+        };
+        //未初始化的窗口不累加
+        assert_eq!(
+            RollingVolume::columns(300, start),
+            vec!["volume24", "volume48"]
+        );
+        assert_eq!(RollingVolume::columns(250, start), vec!["volume48"]);
+        assert!(RollingVolume::columns(199, start).is_empty());
+    }
+}